@@ -59,6 +59,11 @@ thread_local! {
         MEMORY_MANAGER.with(|m| m.get(MemoryId::new(1)))
     );
     
+    // "<caller>:<idempotency key>" -> streams created by that batch
+    static STREAM_BATCHES: StableBTreeMap<String, Vec<StreamId>, Memory> = StableBTreeMap::init(
+        MEMORY_MANAGER.with(|m| m.get(MemoryId::new(2)))
+    );
+    
     static STREAM_COUNTER: std::cell::RefCell<StreamId> = std::cell::RefCell::new(0);
 }
 
@@ -134,8 +139,17 @@ fn create_stream(
 }
 
 #[update]
-fn create_streams(payouts: Vec<(Principal, u64)>) -> Result<Vec<StreamId>, String> {
+fn create_streams(payouts: Vec<(Principal, u64)>, idempotency_key: Option<String>) -> Result<Vec<StreamId>, String> {
     let caller = ic_cdk::caller();
+    
+    // A retried batch returns the streams it already created instead of paying twice
+    let batch_key = idempotency_key.map(|key| format!("{}:{}", caller.to_text(), key));
+    if let Some(ref batch_key) = batch_key {
+        if let Some(stream_ids) = STREAM_BATCHES.with(|batches| batches.get(batch_key)) {
+            return Ok(stream_ids);
+        }
+    }
+    
     let mut stream_ids = Vec::new();
     
     for (recipient, amount) in payouts {
@@ -150,6 +164,12 @@ fn create_streams(payouts: Vec<(Principal, u64)>) -> Result<Vec<StreamId>, Strin
         stream_ids.push(stream_id);
     }
     
+    if let Some(batch_key) = batch_key {
+        STREAM_BATCHES.with(|batches| {
+            batches.insert(batch_key, stream_ids.clone());
+        });
+    }
+    
     Ok(stream_ids)
 }
 
@@ -14,6 +14,12 @@ use ic_stable_structures::{
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 type IdCell = ic_stable_structures::Cell<u64, Memory>;
+type PrincipalCell = ic_stable_structures::Cell<Option<Principal>, Memory>;
+
+const VAULT_WASM: &[u8] = include_bytes!("../../vault/target/wasm32-unknown-unknown/release/vault.wasm");
+
+/// Longest funding window a campaign may ask for (one year)
+const MAX_FUNDING_DURATION_SECONDS: u64 = 365 * 24 * 60 * 60;
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct CampaignMetadata {
@@ -21,13 +27,26 @@ pub struct CampaignMetadata {
     pub title: String,
     pub description: String,
     pub funding_goal: u64,
+    pub funding_deadline: u64,
     pub revenue_share_percentage: u8, // 1-100
     pub oracle_endpoints: Vec<String>,
+    pub milestones: Vec<MilestoneConfig>,
+    /// Oracle and DAO the vault was set up with; only they or the factory can change them
+    pub oracle_canister: Option<Principal>,
+    pub dao_canister: Option<Principal>,
     pub vault_canister_id: Option<Principal>,
     pub created_at: u64,
     pub status: CampaignStatus,
 }
 
+/// A release of raised funds to the creator, gated by backer review in the vault.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct MilestoneConfig {
+    pub title: String,
+    pub description: String,
+    pub release_bps: u64, // share of raised funds; all milestones add up to 10000
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum CampaignStatus {
     Draft,
@@ -47,6 +66,15 @@ thread_local! {
     static CAMPAIGNS: StableBTreeMap<u64, CampaignMetadata, Memory> = StableBTreeMap::init(
         MEMORY_MANAGER.with(|m| m.get(MemoryId::new(1)))
     );
+    
+    // Protocol oracle and DAO handed to every new vault
+    static ORACLE_CANISTER: PrincipalCell = PrincipalCell::init(
+        MEMORY_MANAGER.with(|m| m.get(MemoryId::new(2))), None
+    ).expect("Failed to initialize oracle canister");
+    
+    static DAO_CANISTER: PrincipalCell = PrincipalCell::init(
+        MEMORY_MANAGER.with(|m| m.get(MemoryId::new(3))), None
+    ).expect("Failed to initialize DAO canister");
 }
 
 #[init]
@@ -59,8 +87,10 @@ async fn create_campaign(
     title: String,
     description: String,
     funding_goal: u64,
+    funding_duration_seconds: u64,
     revenue_share_percentage: u8,
     oracle_endpoints: Vec<String>,
+    milestones: Vec<MilestoneConfig>,
 ) -> Result<u64, String> {
     let caller = ic_cdk::caller();
     
@@ -68,6 +98,24 @@ async fn create_campaign(
         return Err("Revenue share must be between 1-100%".to_string());
     }
     
+    if funding_duration_seconds == 0 {
+        return Err("Funding duration must be greater than 0".to_string());
+    }
+    
+    if funding_duration_seconds > MAX_FUNDING_DURATION_SECONDS {
+        return Err("Funding duration cannot exceed one year".to_string());
+    }
+    
+    let created_at = ic_cdk::api::time();
+    let funding_deadline = funding_duration_seconds.checked_mul(1_000_000_000)
+        .and_then(|duration| created_at.checked_add(duration))
+        .ok_or_else(|| "Funding deadline is out of range".to_string())?;
+    
+    // An empty list releases all funds with a single milestone
+    if !milestones.is_empty() && milestones.iter().map(|m| m.release_bps as u128).sum::<u128>() != 10_000 {
+        return Err("Milestone releases must add up to 100%".to_string());
+    }
+    
     // Generate unique campaign ID
     let campaign_id = CAMPAIGN_COUNTER.with(|counter| {
         let current = counter.get();
@@ -82,10 +130,14 @@ async fn create_campaign(
         title: title.clone(),
         description,
         funding_goal,
+        funding_deadline,
         revenue_share_percentage,
         oracle_endpoints,
+        milestones,
+        oracle_canister: ORACLE_CANISTER.with(|cell| *cell.get()),
+        dao_canister: DAO_CANISTER.with(|cell| *cell.get()),
         vault_canister_id: None,
-        created_at: ic_cdk::api::time(),
+        created_at,
         status: CampaignStatus::Draft,
     };
     
@@ -133,12 +185,10 @@ async fn create_vault_canister(
         .map_err(|e| format!("Failed to create canister: {:?}", e))?;
     
     // Install vault code
-    let vault_wasm = include_bytes!("../../vault/target/wasm32-unknown-unknown/release/vault.wasm");
-    
     let install_args = InstallCodeArgument {
         mode: CanisterInstallMode::Install,
         canister_id,
-        wasm_module: vault_wasm.to_vec(),
+        wasm_module: VAULT_WASM.to_vec(),
         arg: candid::encode_args((campaign_id, metadata)).unwrap(),
     };
     
@@ -149,6 +199,89 @@ async fn create_vault_canister(
     Ok(canister_id)
 }
 
+/// Upgrades a campaign's vault in place to the vault wasm bundled with this factory.
+/// Vault state survives the upgrade through its stable-memory hooks.
+#[update]
+async fn upgrade_vault(campaign_id: u64) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only factory controllers can upgrade vaults".to_string());
+    }
+    
+    let vault_id = CAMPAIGNS.with(|campaigns| campaigns.get(&campaign_id))
+        .ok_or_else(|| "Campaign not found".to_string())?
+        .vault_canister_id
+        .ok_or_else(|| "Campaign has no vault canister".to_string())?;
+    
+    let install_args = InstallCodeArgument {
+        mode: CanisterInstallMode::Upgrade(None),
+        canister_id: vault_id,
+        wasm_module: VAULT_WASM.to_vec(),
+        arg: candid::encode_args(()).unwrap(),
+    };
+    
+    install_code(install_args)
+        .await
+        .map_err(|e| format!("Failed to upgrade vault code: {:?}", e))?;
+    
+    ic_cdk::println!("Vault {} for campaign {} upgraded", vault_id.to_text(), campaign_id);
+    Ok(())
+}
+
+/// Sets the oracle and DAO new vaults are created with. Only factory controllers can
+/// do this; existing vaults pick them up through `sync_vault_canisters`.
+#[update]
+fn set_protocol_canisters(oracle: Option<Principal>, dao: Option<Principal>) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only factory controllers can set protocol canisters".to_string());
+    }
+    
+    ORACLE_CANISTER.with(|cell| cell.set(oracle)).expect("Failed to set oracle canister");
+    DAO_CANISTER.with(|cell| cell.set(dao)).expect("Failed to set DAO canister");
+    Ok(())
+}
+
+/// Points a campaign's vault at the factory's current oracle and DAO.
+#[update]
+async fn sync_vault_canisters(campaign_id: u64) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only factory controllers can update vault canisters".to_string());
+    }
+    
+    let campaign = CAMPAIGNS.with(|campaigns| campaigns.get(&campaign_id))
+        .ok_or_else(|| "Campaign not found".to_string())?;
+    let vault_id = campaign.vault_canister_id
+        .ok_or_else(|| "Campaign has no vault canister".to_string())?;
+    let oracle = ORACLE_CANISTER.with(|cell| *cell.get());
+    let dao = DAO_CANISTER.with(|cell| *cell.get());
+    
+    let result: CallResult<(Result<(), String>,)> = ic_cdk::api::call::call(
+        vault_id,
+        "set_canister_refs",
+        (None::<Principal>, None::<Principal>, oracle, None::<Principal>, dao),
+    ).await;
+    
+    match result {
+        Ok((Ok(()),)) => {}
+        Ok((Err(e),)) => return Err(e),
+        Err(e) => return Err(format!("Failed to call vault canister: {:?}", e)),
+    }
+    
+    CAMPAIGNS.with(|campaigns| {
+        if let Some(mut campaign) = campaigns.get(&campaign_id) {
+            campaign.oracle_canister = oracle;
+            campaign.dao_canister = dao;
+            campaigns.insert(campaign_id, campaign);
+        }
+    });
+    Ok(())
+}
+
 #[query]
 fn get_campaign(campaign_id: u64) -> Option<CampaignMetadata> {
     CAMPAIGNS.with(|campaigns| campaigns.get(&campaign_id))
@@ -180,20 +313,65 @@ fn get_active_campaigns() -> Vec<(u64, CampaignMetadata)> {
 }
 
 #[update]
-fn update_campaign_status(campaign_id: u64, status: CampaignStatus) -> Result<(), String> {
+async fn update_campaign_status(campaign_id: u64, status: CampaignStatus) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    
+    let campaign = CAMPAIGNS.with(|campaigns| campaigns.get(&campaign_id))
+        .ok_or_else(|| "Campaign not found".to_string())?;
+    
+    if campaign.creator != caller {
+        return Err("Only campaign creator can update status".to_string());
+    }
+    
+    // Cancelling a campaign opens refunds on its vault before the status is recorded.
+    // The vault only accepts this while it is still funding; after that refunds are
+    // the DAO's call, and the cancellation is rejected.
+    if status == CampaignStatus::Cancelled && campaign.status != CampaignStatus::Cancelled {
+        if let Some(vault_id) = campaign.vault_canister_id {
+            initiate_vault_refund(vault_id).await?;
+        }
+    }
+    
+    CAMPAIGNS.with(|campaigns| {
+        if let Some(mut campaign) = campaigns.get(&campaign_id) {
+            campaign.status = status;
+            campaigns.insert(campaign_id, campaign);
+        }
+    });
+    
+    Ok(())
+}
+
+/// Called by a campaign's vault once its revenue-share term has ended.
+#[update]
+fn complete_campaign(campaign_id: u64) -> Result<(), String> {
     let caller = ic_cdk::caller();
     
     CAMPAIGNS.with(|campaigns| {
         if let Some(mut campaign) = campaigns.get(&campaign_id) {
-            if campaign.creator != caller {
-                return Err("Only campaign creator can update status".to_string());
+            if campaign.vault_canister_id != Some(caller) {
+                return Err("Only the campaign's vault can complete it".to_string());
             }
             
-            campaign.status = status;
+            campaign.status = CampaignStatus::Completed;
             campaigns.insert(campaign_id, campaign);
             Ok(())
         } else {
             Err("Campaign not found".to_string())
         }
     })
+}
+
+async fn initiate_vault_refund(vault_id: Principal) -> Result<(), String> {
+    let result: CallResult<(Result<(), String>,)> = ic_cdk::api::call::call(
+        vault_id,
+        "initiate_refund",
+        (),
+    ).await;
+    
+    match result {
+        Ok((Ok(()),)) => Ok(()),
+        Ok((Err(e),)) => Err(e),
+        Err(e) => Err(format!("Failed to call vault canister: {:?}", e)),
+    }
 }
\ No newline at end of file
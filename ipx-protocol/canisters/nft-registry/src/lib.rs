@@ -17,9 +17,10 @@ pub struct TokenMetadata {
     pub campaign_id: u64,
     pub vault_canister: Principal,
     pub investment_amount: u64,
-    pub share_percentage: f64,
+    pub share: u128, // fraction of the campaign goal, 1e18 == 100%
     pub metadata_json: String,
     pub created_at: u64,
+    pub matured: bool, // revenue-share term of the backing vault has ended
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -58,6 +59,11 @@ thread_local! {
         MEMORY_MANAGER.with(|m| m.get(MemoryId::new(2)))
     );
     
+    // "<vault>:<idempotency key>" -> token minted for that request
+    static MINT_KEYS: StableBTreeMap<String, TokenId, Memory> = StableBTreeMap::init(
+        MEMORY_MANAGER.with(|m| m.get(MemoryId::new(3)))
+    );
+    
     static TOKEN_COUNTER: std::cell::RefCell<TokenId> = std::cell::RefCell::new(0);
     
     static COLLECTION_METADATA: std::cell::RefCell<CollectionMetadata> = std::cell::RefCell::new(
@@ -104,6 +110,16 @@ fn icrc7_owner_of(token_id: TokenId) -> Option<Principal> {
     })
 }
 
+/// Owners of several tokens in one call, in the order requested.
+#[query]
+fn owners_of(token_ids: Vec<TokenId>) -> Vec<Option<Principal>> {
+    TOKENS.with(|tokens| {
+        token_ids.iter()
+            .map(|token_id| tokens.get(token_id).map(|token| token.owner))
+            .collect()
+    })
+}
+
 #[query]
 fn icrc7_balance_of(owner: Principal) -> u64 {
     TOKENS.with(|tokens| {
@@ -147,6 +163,7 @@ fn icrc7_transfer(args: TransferArgs) -> Result<TokenId, String> {
             
             // Update ownership
             token_data.owner = args.to;
+            let vault = token_data.vault_canister;
             
             TOKENS.with(|tokens| {
                 tokens.insert(args.token_id, token_data);
@@ -160,6 +177,21 @@ fn icrc7_transfer(args: TransferArgs) -> Result<TokenId, String> {
             ic_cdk::println!("Token {} transferred from {} to {}", 
                 args.token_id, args.from.to_text(), args.to.to_text());
             
+            // The vault attributes revenue from now on to the new owner; if this is lost
+            // it picks the owner up at the next payout
+            let (token_id, to) = (args.token_id, args.to);
+            ic_cdk::spawn(async move {
+                let result: ic_cdk::api::call::CallResult<(Result<(), String>,)> = ic_cdk::call(
+                    vault,
+                    "notify_position_transfer",
+                    (token_id, to),
+                ).await;
+                
+                if let Err(e) = result.map_err(|e| format!("{:?}", e)).and_then(|(r,)| r) {
+                    ic_cdk::println!("Failed to notify vault of token {} transfer: {}", token_id, e);
+                }
+            });
+            
             Ok(args.token_id)
         }
         None => Err("Token not found".to_string()),
@@ -218,12 +250,24 @@ fn mint(
     campaign_id: u64,
     vault_canister: Principal,
     investment_amount: u64,
-    share_percentage: f64,
+    share: u128,
     metadata_json: String,
+    idempotency_key: Option<String>,
 ) -> Result<TokenId, String> {
     let caller = ic_cdk::caller();
     
-
+    // Positions are minted by the vault that holds the investment
+    if caller != vault_canister {
+        return Err("Only the vault canister can mint its positions".to_string());
+    }
+    
+    // A retried mint returns the token it already created
+    let mint_key = idempotency_key.map(|key| format!("{}:{}", caller.to_text(), key));
+    if let Some(ref mint_key) = mint_key {
+        if let Some(token_id) = MINT_KEYS.with(|keys| keys.get(mint_key)) {
+            return Ok(token_id);
+        }
+    }
     
     let token_id = TOKEN_COUNTER.with(|counter| {
         let current = *counter.borrow();
@@ -238,15 +282,22 @@ fn mint(
         campaign_id,
         vault_canister,
         investment_amount,
-        share_percentage,
+        share,
         metadata_json,
         created_at: ic_cdk::api::time(),
+        matured: false,
     };
     
     TOKENS.with(|tokens| {
         tokens.insert(token_id, token_metadata);
     });
     
+    if let Some(mint_key) = mint_key {
+        MINT_KEYS.with(|keys| {
+            keys.insert(mint_key, token_id);
+        });
+    }
+    
     // Update total supply
     COLLECTION_METADATA.with(|metadata| {
         metadata.borrow_mut().total_supply += 1;
@@ -257,3 +308,28 @@ fn mint(
     Ok(token_id)
 }
 
+/// Called by a vault when its revenue-share term ends; marks every token it backs as matured.
+#[update]
+fn mark_vault_matured() -> Result<u64, String> {
+    let caller = ic_cdk::caller();
+    
+    let matured_tokens: Vec<TokenMetadata> = TOKENS.with(|tokens| {
+        tokens.iter()
+            .filter(|(_, token)| token.vault_canister == caller && !token.matured)
+            .map(|(_, token)| token)
+            .collect()
+    });
+    
+    let count = matured_tokens.len() as u64;
+    
+    TOKENS.with(|tokens| {
+        for mut token in matured_tokens {
+            token.matured = true;
+            tokens.insert(token.token_id, token);
+        }
+    });
+    
+    ic_cdk::println!("{} tokens matured for vault {}", count, caller.to_text());
+    
+    Ok(count)
+}
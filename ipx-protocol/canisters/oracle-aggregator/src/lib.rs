@@ -30,6 +30,62 @@ pub struct ApiEndpoint {
     pub url: String,
     pub auth_header: Option<String>,
     pub data_path: String, // JSON path to extract revenue data
+    #[serde(default = "default_currency")]
+    pub currency: String, // Currency the platform reports revenue in
+    #[serde(default = "default_period")]
+    pub period: String, // Period the endpoint's totals cover, e.g. "2025-03" or "lifetime"
+}
+
+fn default_period() -> String {
+    "lifetime".to_string()
+}
+
+// Platform extractors report in cents
+fn default_decimals() -> u8 {
+    2
+}
+
+fn default_currency() -> String {
+    "USD".to_string()
+}
+
+/// Revenue amount in `currency`, with `decimals` decimal places, as the vault expects it
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RevenueAmount {
+    pub amount: u64,
+    pub currency: String,
+    pub decimals: u8,
+}
+
+/// Platform totals are running totals, so they go to the vault as cumulative snapshots
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum ReportKind {
+    Delta,
+    CumulativeSnapshot,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RevenueReport {
+    pub revenue: RevenueAmount,
+    pub platform: String,
+    pub period: String,
+    pub external_id: String,
+    pub kind: ReportKind,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum VaultOperation {
+    Investing,
+    RevenueIngestion,
+    Distribution,
+    Refunds,
+}
+
+/// Error the vault returns from `update_revenue`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum OperationError {
+    Paused { operation: VaultOperation, since: u64, reason: String },
+    Failed { message: String },
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -38,6 +94,10 @@ pub struct RevenueData {
     pub platform: String,
     pub amount: u64,
     pub currency: String,
+    #[serde(default = "default_decimals")]
+    pub decimals: u8,
+    #[serde(default = "default_period")]
+    pub period: String,
     pub timestamp: u64,
     pub raw_data: String,
     pub verified: bool,
@@ -123,10 +183,11 @@ async fn fetch_revenue_data(campaign_id: u64) -> Result<Vec<RevenueData>, String
                 configs.insert(campaign_id, config);
             });
             
-            // Send updates to vault canister
-            if !results.is_empty() {
-                let total_revenue: u64 = results.iter().map(|r| r.amount).sum();
-                let _ = update_vault_revenue(config.vault_canister, total_revenue).await;
+            // Send each platform's total to the vault; it only counts growth since the last report
+            for data in &results {
+                if let Err(e) = update_vault_revenue(config.vault_canister, revenue_report(data)).await {
+                    ic_cdk::println!("Failed to report {} revenue to vault: {}", data.platform, e);
+                }
             }
             
             Ok(results)
@@ -135,6 +196,22 @@ async fn fetch_revenue_data(campaign_id: u64) -> Result<Vec<RevenueData>, String
     }
 }
 
+/// The vault report for a fetched total. The id is the period's running total, so
+/// fetching an unchanged total again names the same event and the vault drops it.
+fn revenue_report(data: &RevenueData) -> RevenueReport {
+    RevenueReport {
+        revenue: RevenueAmount {
+            amount: data.amount,
+            currency: data.currency.clone(),
+            decimals: data.decimals,
+        },
+        platform: data.platform.clone(),
+        period: data.period.clone(),
+        external_id: format!("total-{}", data.amount),
+        kind: ReportKind::CumulativeSnapshot,
+    }
+}
+
 async fn fetch_from_endpoint(campaign_id: u64, endpoint: &ApiEndpoint) -> Result<RevenueData, String> {
     let mut headers = vec![
         HttpHeader {
@@ -200,7 +277,9 @@ fn parse_revenue_from_response(
         campaign_id,
         platform: endpoint.platform.clone(),
         amount,
-        currency: "USD".to_string(), // Default to USD, could be configurable
+        currency: endpoint.currency.clone(),
+        decimals: default_decimals(),
+        period: endpoint.period.clone(),
         timestamp: ic_cdk::api::time(),
         raw_data: response.to_string(),
         verified: true, // TODO: Add verification logic
@@ -232,16 +311,17 @@ fn extract_substack_revenue(json: &Value) -> Result<u64, String> {
         .ok_or_else(|| "Failed to extract Substack revenue".to_string())
 }
 
-async fn update_vault_revenue(vault_canister: Principal, amount: u64) -> Result<(), String> {
-    let result: CallResult<(Result<(), String>,)> = ic_cdk::api::call::call(
+async fn update_vault_revenue(vault_canister: Principal, report: RevenueReport) -> Result<(), String> {
+    let result: CallResult<(Result<(), OperationError>,)> = ic_cdk::api::call::call(
         vault_canister,
         "update_revenue",
-        (amount, "oracle_aggregated".to_string(), true),
+        (report,),
     ).await;
     
     match result {
         Ok((Ok(()),)) => Ok(()),
-        Ok((Err(e),)) => Err(e),
+        Ok((Err(OperationError::Paused { reason, .. }),)) => Err(format!("Vault revenue ingestion is paused: {}", reason)),
+        Ok((Err(OperationError::Failed { message }),)) => Err(message),
         Err(e) => Err(format!("Failed to call vault: {:?}", e)),
     }
 }
@@ -282,4 +362,40 @@ fn deactivate_oracle(campaign_id: u64) -> Result<(), String> {
             Err("Oracle config not found".to_string())
         }
     })
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fetched(amount: u64, timestamp: u64) -> RevenueData {
+        RevenueData {
+            campaign_id: 1,
+            platform: "spotify".to_string(),
+            amount,
+            currency: "USD".to_string(),
+            decimals: 2,
+            period: "2025-03".to_string(),
+            timestamp,
+            raw_data: String::new(),
+            verified: true,
+        }
+    }
+
+    #[test]
+    fn refetching_an_unchanged_total_names_the_same_event() {
+        let first = revenue_report(&fetched(12_500, 1_000));
+        let again = revenue_report(&fetched(12_500, 2_000));
+
+        assert_eq!(first.platform, again.platform);
+        assert_eq!(first.period, again.period);
+        assert_eq!(first.external_id, again.external_id);
+    }
+
+    #[test]
+    fn a_grown_total_is_a_new_event() {
+        let first = revenue_report(&fetched(12_500, 1_000));
+        let grown = revenue_report(&fetched(13_000, 2_000));
+
+        assert_ne!(first.external_id, grown.external_id);
+    }
+}
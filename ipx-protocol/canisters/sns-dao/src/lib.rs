@@ -46,7 +46,8 @@ pub enum ProposalType {
     },
     DisputeResolution {
         campaign_id: u64,
-        disputed_amount: u64,
+        revenue_index: u64,
+        action: DisputeAction,
         resolution: String,
     },
     OracleUpdate {
@@ -59,6 +60,15 @@ pub enum ProposalType {
     },
 }
 
+/// What a dispute proposal does to the disputed vault revenue entry.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum DisputeAction {
+    Freeze,
+    Unfreeze,
+    Reverse,
+    Adjust { corrected_amount: u64 },
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum ProposalStatus {
     Open,
@@ -313,8 +323,8 @@ async fn execute_proposal(proposal_id: ProposalId) -> Result<(), String> {
         ProposalType::UpdateRevenueShare { campaign_id, new_percentage } => {
             execute_revenue_share_update(*campaign_id, *new_percentage).await
         }
-        ProposalType::DisputeResolution { campaign_id, disputed_amount, .. } => {
-            execute_dispute_resolution(*campaign_id, *disputed_amount).await
+        ProposalType::DisputeResolution { campaign_id, revenue_index, action, .. } => {
+            execute_dispute_resolution(*campaign_id, *revenue_index, action.clone()).await
         }
         ProposalType::OracleUpdate { campaign_id, new_endpoints } => {
             execute_oracle_update(*campaign_id, new_endpoints.clone()).await
@@ -390,12 +400,12 @@ async fn execute_revenue_share_update(campaign_id: u64, new_percentage: u8) -> R
     }
 }
 
-async fn execute_dispute_resolution(campaign_id: u64, disputed_amount: u64) -> Result<(), String> {
+async fn execute_dispute_resolution(campaign_id: u64, revenue_index: u64, action: DisputeAction) -> Result<(), String> {
     let vault_canister = get_vault_canister(campaign_id)?;
     let result: CallResult<(Result<(), String>,)> = ic_cdk::api::call::call(
         vault_canister,
         "resolve_dispute",
-        (disputed_amount,),
+        (revenue_index, action),
     ).await;
     
     match result {
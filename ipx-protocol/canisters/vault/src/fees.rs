@@ -0,0 +1,72 @@
+// Protocol and creator fees.
+//
+// Protocol fees are charged at three points: on raised funds as milestones release
+// them to the creator, on the backers' share of each revenue update before it enters
+// the waterfall, and on every payout. They accrue to the protocol treasury and are
+// sent there by `collect_fees`: raise fees from escrow over the ledger, revenue and
+// payout fees through the stream canister like any other payout. The creator fee is
+// taken from the backers' revenue share next to the protocol revenue fee and simply
+// stays with the creator.
+
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+/// Highest rate any single fee can be set to (20%)
+pub const MAX_FEE_BPS: u64 = 2_000;
+
+/// Protocol fee rates in basis points. Set by the DAO; changes apply from then on.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ProtocolFees {
+    pub treasury: Option<Principal>,
+    pub raise_fee_bps: u64,
+    pub revenue_fee_bps: u64,
+    pub payout_fee_bps: u64,
+}
+
+/// Lifetime fee totals. Protocol fees are owed to the treasury until collected.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct FeeTotals {
+    pub raise: u64,
+    pub revenue: u64,
+    pub payout: u64,
+    pub creator: u64,
+    /// Raise fees sent to the treasury from escrow
+    pub escrow_collected: u64,
+    /// Revenue and payout fees streamed to the treasury
+    pub revenue_collected: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct FeeAccounting {
+    pub protocol_fees: ProtocolFees,
+    pub creator_fee_bps: u64,
+    pub totals: FeeTotals,
+    pub escrow_owed: u64,
+    pub revenue_owed: u64,
+}
+
+impl ProtocolFees {
+    pub fn validate(&self) -> Result<(), String> {
+        let rates = [self.raise_fee_bps, self.revenue_fee_bps, self.payout_fee_bps];
+        if rates.iter().any(|bps| *bps > MAX_FEE_BPS) {
+            return Err(format!("Fee rates cannot exceed {} bps", MAX_FEE_BPS));
+        }
+
+        let charges_fees = rates.iter().any(|bps| *bps > 0);
+        if charges_fees && self.treasury.is_none() {
+            return Err("A treasury is required to charge fees".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+impl FeeTotals {
+    pub fn escrow_owed(&self) -> u64 {
+        self.raise.saturating_sub(self.escrow_collected)
+    }
+
+    pub fn revenue_owed(&self) -> u64 {
+        (self.revenue + self.payout).saturating_sub(self.revenue_collected)
+    }
+}
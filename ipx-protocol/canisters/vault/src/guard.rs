@@ -0,0 +1,64 @@
+// Locks that keep async vault methods from interleaving across their awaits.
+//
+// A `Guard` holds its lock for as long as it is alive and releases it when dropped.
+// If a call traps after an await, the CDK drops the suspended future during cleanup,
+// so its guards are released then too. Locks live on the heap only; an upgrade can
+// only happen with no calls in flight, so none need to survive it.
+
+use candid::Principal;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Lock {
+    /// Investing, refund and payout claims by one principal
+    Principal(Principal),
+    /// Payout batches, manual or from the distribution timer
+    Distribution,
+    FeeCollection,
+    /// Minting the position NFT of one lot
+    Mint(Principal, u32),
+    /// Dispatching one outbox item
+    OutboxItem(u64),
+}
+
+thread_local! {
+    static LOCKS: RefCell<BTreeSet<Lock>> = const { RefCell::new(BTreeSet::new()) };
+}
+
+pub struct Guard {
+    lock: Lock,
+}
+
+impl Guard {
+    /// Takes `lock`, or fails if another call holds it.
+    pub fn acquire(lock: Lock) -> Result<Self, String> {
+        if LOCKS.with(|locks| locks.borrow_mut().insert(lock)) {
+            Ok(Guard { lock })
+        } else {
+            Err(lock.busy_message())
+        }
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        LOCKS.with(|locks| locks.borrow_mut().remove(&self.lock));
+    }
+}
+
+pub fn is_held(lock: &Lock) -> bool {
+    LOCKS.with(|locks| locks.borrow().contains(lock))
+}
+
+impl Lock {
+    fn busy_message(&self) -> String {
+        match self {
+            Lock::Principal(principal) => format!("Another call from {} is still in progress", principal.to_text()),
+            Lock::Distribution => "A payout distribution is already in progress".to_string(),
+            Lock::FeeCollection => "Fee collection is already in progress".to_string(),
+            Lock::Mint(..) => "NFT mint already in progress for this investment".to_string(),
+            Lock::OutboxItem(id) => format!("Outbox item {} is already in flight", id),
+        }
+    }
+}
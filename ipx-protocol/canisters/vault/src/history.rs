@@ -0,0 +1,108 @@
+// Paged and aggregated views of the revenue history and the backer list.
+//
+// Both grow without bound, so clients read them a page at a time instead of in one
+// reply that would eventually exceed the message size limit. Revenue pages follow
+// log order, which is time order; backer pages run from the largest position down
+// and resume from the last backer returned. Monthly totals per source are kept up
+// to date as revenue is recorded, with corrections counted in the month of the
+// entry they correct.
+
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+use crate::{BackerInfo, RevenueUpdate};
+
+/// Most entries a single page returns
+pub const MAX_PAGE_SIZE: u32 = 500;
+/// Most revenue entries a filtered query inspects before returning a partial page
+pub const MAX_REVENUE_SCAN: u64 = 10_000;
+
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RevenueFilter {
+    /// Inclusive lower bound on the entry timestamp
+    pub from: Option<u64>,
+    /// Exclusive upper bound on the entry timestamp
+    pub to: Option<u64>,
+    pub source: Option<String>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RevenuePage {
+    /// Matching entries with their index in the revenue log
+    pub entries: Vec<(u64, RevenueUpdate)>,
+    /// Index to pass as `start` for the next page; `None` once the range is exhausted
+    pub next_start: Option<u64>,
+}
+
+/// Position in the backer list: the last backer a page returned.
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct BackerCursor {
+    pub amount_invested: u64,
+    pub backer: Principal,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct BackerPage {
+    pub backers: Vec<(Principal, BackerInfo)>,
+    pub next_cursor: Option<BackerCursor>,
+}
+
+/// Key of the monthly revenue totals; orders by month, then source.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MonthKey {
+    pub month: u32,
+    pub source: String,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MonthlyRevenue {
+    /// Calendar month in UTC as `YYYYMM`
+    pub month: u32,
+    pub source: String,
+    pub amount: u64,
+    /// Entries counted in `amount`; reversed entries drop out
+    pub entries: u32,
+}
+
+impl RevenueFilter {
+    pub fn matches(&self, entry: &RevenueUpdate) -> bool {
+        self.from.is_none_or(|from| entry.timestamp >= from)
+            && self.source.as_ref().is_none_or(|source| *source == entry.source)
+            && self.to.is_none_or(|to| entry.timestamp < to)
+    }
+
+    /// True once `entry` and everything logged after it falls past the range.
+    pub fn is_past(&self, entry: &RevenueUpdate) -> bool {
+        self.to.is_some_and(|to| entry.timestamp >= to)
+    }
+}
+
+/// The UTC calendar month of a nanosecond timestamp, as `YYYYMM`.
+pub fn month_of(timestamp: u64) -> u32 {
+    // Civil-from-days over 400-year eras, with years starting on 1 March
+    let days = (timestamp / NANOS_PER_DAY) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year * 100 + month) as u32
+}
+
+/// Index of the first of `len` time-ordered entries logged at or after `from`.
+pub fn first_at_or_after(len: u64, from: u64, timestamp_at: impl Fn(u64) -> u64) -> u64 {
+    let (mut low, mut high) = (0, len);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if timestamp_at(mid) < from {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    low
+}
@@ -0,0 +1,145 @@
+// Idempotent revenue reports.
+//
+// Every report names the event it describes by platform, reporting period and the
+// platform's own id for it; a report for an event that was already recorded is
+// rejected, so retries and repeated pushes cannot count revenue twice. A report is
+// either a delta, new revenue on its own, or a cumulative snapshot of everything the
+// platform has paid for the period so far, of which only the growth since the
+// previous snapshot is new.
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+use crate::rates::RevenueAmount;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum ReportKind {
+    Delta,
+    CumulativeSnapshot,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RevenueReport {
+    pub revenue: RevenueAmount,
+    pub platform: String,
+    /// Reporting period the figure belongs to, e.g. `2025-03`
+    pub period: String,
+    /// The platform's id for this payment or statement
+    pub external_id: String,
+    pub kind: ReportKind,
+}
+
+/// Identifies a reported event; each is recorded at most once.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RevenueEventKey {
+    pub platform: String,
+    pub period: String,
+    pub external_id: String,
+}
+
+/// The running total cumulative snapshots are measured against.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SnapshotKey {
+    pub platform: String,
+    pub period: String,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RecordedEvent {
+    /// Revenue entry the event produced; `None` if it brought no new revenue
+    pub revenue_index: Option<u64>,
+    pub recorded_at: u64,
+}
+
+impl RevenueReport {
+    pub fn validate(&self) -> Result<(), String> {
+        self.revenue.validate()?;
+        if self.platform.is_empty() || self.period.is_empty() || self.external_id.is_empty() {
+            return Err("Revenue reports need a platform, period and external id".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn event_key(&self) -> RevenueEventKey {
+        RevenueEventKey {
+            platform: self.platform.clone(),
+            period: self.period.clone(),
+            external_id: self.external_id.clone(),
+        }
+    }
+
+    pub fn snapshot_key(&self) -> SnapshotKey {
+        SnapshotKey {
+            platform: self.platform.clone(),
+            period: self.period.clone(),
+        }
+    }
+
+    /// Revenue the report adds, given the period's previous snapshot. `None` when a
+    /// snapshot shows no growth.
+    pub fn new_revenue(&self, previous: Option<&RevenueAmount>) -> Result<Option<u64>, String> {
+        if self.kind == ReportKind::Delta {
+            return Ok(Some(self.revenue.amount));
+        }
+
+        let Some(previous) = previous else {
+            return Ok(Some(self.revenue.amount).filter(|amount| *amount > 0));
+        };
+
+        if previous.currency != self.revenue.currency || previous.decimals != self.revenue.decimals {
+            return Err(format!(
+                "Snapshots for {} {} are reported in {} with {} decimals",
+                self.platform, self.period, previous.currency, previous.decimals
+            ));
+        }
+
+        match self.revenue.amount.checked_sub(previous.amount) {
+            Some(0) => Ok(None),
+            Some(growth) => Ok(Some(growth)),
+            None => Err(format!(
+                "Snapshot of {} is below the previous snapshot of {}; corrections go through a dispute",
+                self.revenue.amount, previous.amount
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(amount: u64) -> RevenueReport {
+        RevenueReport {
+            revenue: RevenueAmount { amount, currency: "USD".to_string(), decimals: 2 },
+            platform: "spotify".to_string(),
+            period: "2025-03".to_string(),
+            external_id: format!("total-{}", amount),
+            kind: ReportKind::CumulativeSnapshot,
+        }
+    }
+
+    #[test]
+    fn repeated_snapshot_is_applied_once() {
+        let first = snapshot(12_500);
+        let again = snapshot(12_500);
+
+        assert_eq!(first.event_key(), again.event_key());
+        assert_eq!(first.new_revenue(None), Ok(Some(12_500)));
+        // Even if the key were not caught, the repeat brings no growth
+        assert_eq!(again.new_revenue(Some(&first.revenue)), Ok(None));
+    }
+
+    #[test]
+    fn snapshot_growth_is_counted_once() {
+        let first = snapshot(12_500);
+        let grown = snapshot(13_000);
+
+        assert_ne!(first.event_key(), grown.event_key());
+        assert_eq!(grown.new_revenue(Some(&first.revenue)), Ok(Some(500)));
+    }
+
+    #[test]
+    fn shrinking_snapshot_is_rejected() {
+        assert!(snapshot(12_000).new_revenue(Some(&snapshot(12_500).revenue)).is_err());
+    }
+}
@@ -0,0 +1,210 @@
+// Append-only double-entry journal of every movement of value through the vault.
+//
+// Escrow is the only account backed by tokens the vault holds on the ledger; its
+// balance must never exceed the vault's ledger balance. Revenue is reported rather
+// than received, so it is debited to `ReportedRevenue` and settled through
+// `StreamedPayouts` as the stream canister pays it out. Every entry's debits equal
+// its credits, so the net balance over all accounts is always zero.
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum JournalAccount {
+    /// Tokens held by the vault on the ledger
+    Escrow,
+    /// Raised principal still owed to backers or not yet released to the creator
+    BackerCapital,
+    /// Revenue reported by the oracle, creator or DAO
+    ReportedRevenue,
+    /// Revenue accrued to backers and not yet paid out
+    BackerPayable,
+    /// Protocol fees not yet sent to the treasury
+    TreasuryPayable,
+    /// Revenue kept by the creator, including the creator fee
+    CreatorRevenue,
+    /// Revenue paid out through the stream canister
+    StreamedPayouts,
+    /// Backer revenue held back until its holdback period ends
+    Reserve,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum JournalKind {
+    Opening,
+    Deposit,
+    Refund,
+    Release,
+    RevenueAccrual,
+    Payout,
+    Fee,
+    Adjustment,
+    ReserveRelease,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct JournalLine {
+    pub account: JournalAccount,
+    pub debit: u64,
+    pub credit: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct JournalEntry {
+    pub kind: JournalKind,
+    pub timestamp: u64,
+    pub memo: String,
+    pub lines: Vec<JournalLine>,
+}
+
+impl JournalEntry {
+    pub fn new(kind: JournalKind, memo: String, timestamp: u64) -> Self {
+        JournalEntry {
+            kind,
+            timestamp,
+            memo,
+            lines: Vec::new(),
+        }
+    }
+
+    pub fn debit(mut self, account: JournalAccount, amount: u64) -> Self {
+        if amount > 0 {
+            self.lines.push(JournalLine { account, debit: amount, credit: 0 });
+        }
+        self
+    }
+
+    pub fn credit(mut self, account: JournalAccount, amount: u64) -> Self {
+        if amount > 0 {
+            self.lines.push(JournalLine { account, debit: 0, credit: amount });
+        }
+        self
+    }
+
+    /// Debits `amount` if positive, credits its magnitude if negative.
+    pub fn signed(self, account: JournalAccount, amount: i128) -> Self {
+        if amount >= 0 {
+            self.debit(account, amount as u64)
+        } else {
+            self.credit(account, amount.unsigned_abs() as u64)
+        }
+    }
+
+    pub fn is_balanced(&self) -> bool {
+        let debits: u128 = self.lines.iter().map(|l| l.debit as u128).sum();
+        let credits: u128 = self.lines.iter().map(|l| l.credit as u128).sum();
+        debits == credits
+    }
+
+    /// The same movement in the opposite direction.
+    pub fn reversed(&self, memo: String, timestamp: u64) -> Self {
+        JournalEntry {
+            kind: JournalKind::Adjustment,
+            timestamp,
+            memo,
+            lines: self.lines.iter()
+                .map(|l| JournalLine { account: l.account, debit: l.credit, credit: l.debit })
+                .collect(),
+        }
+    }
+}
+
+/// Revenue of `amount` split between backers (`credited` now, `reserved` held back),
+/// the protocol and the creator.
+pub fn revenue_accrual(memo: String, timestamp: u64, amount: u64, credited: u64, reserved: u64, protocol_fee: u64) -> JournalEntry {
+    JournalEntry::new(JournalKind::RevenueAccrual, memo, timestamp)
+        .debit(JournalAccount::ReportedRevenue, amount)
+        .credit(JournalAccount::BackerPayable, credited)
+        .credit(JournalAccount::Reserve, reserved)
+        .credit(JournalAccount::TreasuryPayable, protocol_fee)
+        .credit(JournalAccount::CreatorRevenue, amount - credited - reserved - protocol_fee)
+}
+
+/// Held revenue of `amount` released to backers.
+pub fn reserve_release(memo: String, timestamp: u64, amount: u64) -> JournalEntry {
+    JournalEntry::new(JournalKind::ReserveRelease, memo, timestamp)
+        .debit(JournalAccount::Reserve, amount)
+        .credit(JournalAccount::BackerPayable, amount)
+}
+
+/// A payout of `gross` accrued revenue, of which `fee` is kept for the protocol.
+pub fn payout(memo: String, timestamp: u64, gross: u64, fee: u64) -> JournalEntry {
+    JournalEntry::new(JournalKind::Payout, memo, timestamp)
+        .debit(JournalAccount::BackerPayable, gross)
+        .credit(JournalAccount::StreamedPayouts, gross - fee)
+        .credit(JournalAccount::TreasuryPayable, fee)
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct InvariantReport {
+    /// Net debit balance of each account; liabilities and revenue are negative
+    pub balances: Vec<(JournalAccount, i128)>,
+    pub ledger_balance: u64,
+    pub violations: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn net(entries: &[JournalEntry], account: JournalAccount) -> i128 {
+        entries.iter()
+            .flat_map(|entry| &entry.lines)
+            .filter(|line| line.account == account)
+            .map(|line| line.debit as i128 - line.credit as i128)
+            .sum()
+    }
+
+    #[test]
+    fn postings_balance() {
+        let accrual = revenue_accrual("revenue 0".to_string(), 0, 1_000, 240, 60, 30);
+        assert!(accrual.is_balanced());
+        assert_eq!(net(&[accrual], JournalAccount::CreatorRevenue), -670);
+
+        assert!(reserve_release("reserve".to_string(), 0, 60).is_balanced());
+        assert!(payout("payout".to_string(), 0, 300, 3).is_balanced());
+    }
+
+    #[test]
+    fn unbalanced_entry_is_detected() {
+        let entry = JournalEntry::new(JournalKind::Adjustment, String::new(), 0)
+            .debit(JournalAccount::Escrow, 100)
+            .credit(JournalAccount::BackerCapital, 99);
+        assert!(!entry.is_balanced());
+    }
+
+    #[test]
+    fn zero_amounts_post_no_lines() {
+        let accrual = revenue_accrual(String::new(), 0, 1_000, 0, 0, 0);
+        assert_eq!(accrual.lines.len(), 2);
+        assert!(JournalEntry::new(JournalKind::Fee, String::new(), 0).debit(JournalAccount::Escrow, 0).lines.is_empty());
+    }
+
+    #[test]
+    fn reversal_cancels_every_account() {
+        let accrual = revenue_accrual("revenue 0".to_string(), 0, 1_000, 240, 60, 30);
+        let reversal = accrual.reversed("revenue 0 reversed".to_string(), 1);
+
+        assert!(reversal.is_balanced());
+        assert_eq!(reversal.kind, JournalKind::Adjustment);
+        let entries = [accrual, reversal];
+        for account in [
+            JournalAccount::ReportedRevenue,
+            JournalAccount::BackerPayable,
+            JournalAccount::Reserve,
+            JournalAccount::TreasuryPayable,
+            JournalAccount::CreatorRevenue,
+        ] {
+            assert_eq!(net(&entries, account), 0, "{:?}", account);
+        }
+    }
+
+    #[test]
+    fn signed_posts_to_the_matching_side() {
+        let entry = JournalEntry::new(JournalKind::Opening, String::new(), 0)
+            .signed(JournalAccount::Escrow, 500)
+            .signed(JournalAccount::BackerCapital, -500);
+        assert!(entry.is_balanced());
+        assert_eq!(net(&[entry], JournalAccount::BackerCapital), -500);
+    }
+}
@@ -0,0 +1,146 @@
+// Minimal ICRC-1 / ICRC-2 client used by the vault to move campaign funds.
+
+use candid::{CandidType, Nat, Principal};
+use serde::{Deserialize, Serialize};
+
+use crate::call;
+
+pub type Subaccount = [u8; 32];
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Account {
+    pub owner: Principal,
+    pub subaccount: Option<Subaccount>,
+}
+
+impl From<Principal> for Account {
+    fn from(owner: Principal) -> Self {
+        Account {
+            owner,
+            subaccount: None,
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TransferFromArgs {
+    pub spender_subaccount: Option<Subaccount>,
+    pub from: Account,
+    pub to: Account,
+    pub amount: Nat,
+    pub fee: Option<Nat>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum TransferFromError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    InsufficientAllowance { allowance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TransferArg {
+    pub from_subaccount: Option<Subaccount>,
+    pub to: Account,
+    pub amount: Nat,
+    pub fee: Option<Nat>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum TransferError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+/// Pulls `amount` from `from` into the vault's default account using the
+/// allowance the backer granted via `icrc2_approve`. Returns the block index.
+pub async fn transfer_from(
+    ledger: Principal,
+    from: Principal,
+    amount: u64,
+    memo: Vec<u8>,
+) -> Result<u64, String> {
+    let args = TransferFromArgs {
+        spender_subaccount: None,
+        from: Account::from(from),
+        to: Account::from(ic_cdk::api::canister_self()),
+        amount: Nat::from(amount),
+        fee: None,
+        memo: Some(memo),
+        created_at_time: Some(ic_cdk::api::time()),
+    };
+
+    let result: Result<Result<Nat, TransferFromError>, _> =
+        call(ledger, "icrc2_transfer_from", (args,)).await;
+
+    match result {
+        Ok(Ok(block_index)) => to_block_index(block_index),
+        Ok(Err(e)) => Err(format!("Ledger rejected transfer: {:?}", e)),
+        Err(e) => Err(format!("Failed to call ledger: {:?}", e)),
+    }
+}
+
+/// Sends `amount` from the vault's default account to `to`. The ledger fee is
+/// charged on top of `amount`, so callers must account for it.
+pub async fn transfer(
+    ledger: Principal,
+    to: Principal,
+    amount: u64,
+    memo: Vec<u8>,
+) -> Result<u64, String> {
+    let args = TransferArg {
+        from_subaccount: None,
+        to: Account::from(to),
+        amount: Nat::from(amount),
+        fee: None,
+        memo: Some(memo),
+        created_at_time: Some(ic_cdk::api::time()),
+    };
+
+    let result: Result<Result<Nat, TransferError>, _> =
+        call(ledger, "icrc1_transfer", (args,)).await;
+
+    match result {
+        Ok(Ok(block_index)) => to_block_index(block_index),
+        Ok(Err(e)) => Err(format!("Ledger rejected transfer: {:?}", e)),
+        Err(e) => Err(format!("Failed to call ledger: {:?}", e)),
+    }
+}
+
+pub async fn fee(ledger: Principal) -> Result<u64, String> {
+    let result: Result<Nat, _> = call(ledger, "icrc1_fee", ()).await;
+
+    match result {
+        Ok(fee) => u64::try_from(&fee.0).map_err(|_| format!("Ledger fee {} does not fit in u64", fee)),
+        Err(e) => Err(format!("Failed to query ledger fee: {:?}", e)),
+    }
+}
+
+pub async fn balance_of(ledger: Principal, owner: Principal) -> Result<u64, String> {
+    let result: Result<Nat, _> = call(ledger, "icrc1_balance_of", (Account::from(owner),)).await;
+
+    match result {
+        Ok(balance) => u64::try_from(&balance.0).map_err(|_| format!("Ledger balance {} does not fit in u64", balance)),
+        Err(e) => Err(format!("Failed to query ledger balance: {:?}", e)),
+    }
+}
+
+fn to_block_index(block_index: Nat) -> Result<u64, String> {
+    u64::try_from(&block_index.0).map_err(|_| format!("Block index {} does not fit in u64", block_index))
+}
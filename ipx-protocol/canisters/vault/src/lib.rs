@@ -1,12 +1,49 @@
-use ic_cdk::export::{candid::CandidType, Principal};
+use candid::{CandidType, Principal};
 use ic_cdk_macros::*;
-use ic_cdk::api::call::{call, CallResult};
-use std::collections::HashMap;
+use ic_cdk::call::Call;
 use serde::{Deserialize, Serialize};
 use ic_stable_structures::{
-    memory_manager::{MemoryId, MemoryManager, VirtualMemory},
-    DefaultMemoryImpl, StableBTreeMap,
+    memory_manager::{MemoryManager, VirtualMemory},
+    DefaultMemoryImpl, StableBTreeMap, StableCell, StableLog,
 };
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+use std::time::Duration;
+
+mod fees;
+mod guard;
+mod history;
+mod ingestion;
+mod journal;
+mod ledger;
+mod milestones;
+mod outbox;
+mod pause;
+mod rates;
+mod reserve;
+mod rules;
+mod schedule;
+mod shares;
+mod statements;
+mod storage;
+mod waterfall;
+
+use fees::{FeeAccounting, FeeTotals, ProtocolFees};
+use guard::{Guard, Lock};
+use history::{BackerCursor, BackerPage, MonthKey, MonthlyRevenue, RevenueFilter, RevenuePage};
+use ingestion::{RecordedEvent, RevenueEventKey, RevenueReport, SnapshotKey};
+use journal::{InvariantReport, JournalAccount, JournalEntry, JournalKind};
+use milestones::{Milestone, MilestoneConfig, MilestoneStatus};
+use outbox::{Effect, Failure, OutboxItem, OutboxStatus};
+use pause::{Operation, OperationError, PauseEvent, PauseInfo, PauseRole};
+use rates::{Conversion, CurrencyConfig, CurrencyTotal, RevenueAmount, XrcSource};
+use reserve::{ReserveConfig, ReserveHold, ReserveTotals};
+use rules::{AccessLevel, InvestmentRejection, InvestmentRules};
+use schedule::{Schedule, ScheduleStatus, ScheduledJob};
+use statements::{BackerStatement, Granularity, OwnerRecord, PeriodSnapshot, PeriodSummary, StatementLot};
+use storage::{LegacyBackerInfo, PersistedState, SCHEMA_VERSION};
+use waterfall::{TrancheState, WaterfallConfig, WaterfallPreview};
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 
@@ -17,24 +54,128 @@ pub struct VaultState {
     pub title: String,
     pub funding_goal: u64,
     pub current_funding: u64,
+    pub funding_deadline: u64,
+    pub status: VaultStatus,
+    pub total_refunded: u64,
     pub revenue_share_percentage: u8,
     pub total_revenue: u64,
+    #[serde(default)]
+    pub waterfall: WaterfallConfig,
+    #[serde(default)]
+    pub tranches: Vec<TrancheState>,
+    /// Cumulative revenue that has been run through the waterfall
+    #[serde(default)]
+    pub waterfall_revenue: u64,
+    #[serde(default)]
+    pub total_distributable: u64,
+    #[serde(default)]
+    pub total_claimed: u64,
+    #[serde(default)]
+    pub term: RevenueTerm,
+    #[serde(default)]
+    pub term_start: Option<u64>,
+    #[serde(default)]
+    pub matured_at: Option<u64>,
+    #[serde(default)]
+    pub maturity_notified: bool,
+    /// Last backer processed by the `distribute_payouts` keeper
+    #[serde(default)]
+    pub payout_cursor: Option<Principal>,
+    #[serde(default)]
+    pub investment_rules: InvestmentRules,
+    #[serde(default)]
+    pub milestones: Vec<Milestone>,
+    #[serde(default)]
+    pub protocol_fees: ProtocolFees,
+    /// Creator's cut of the backers' revenue share, in basis points
+    #[serde(default)]
+    pub creator_fee_bps: u64,
+    #[serde(default)]
+    pub fee_totals: FeeTotals,
+    /// Net debit balance of each journal account
+    #[serde(default)]
+    pub journal_balances: BTreeMap<JournalAccount, i128>,
+    #[serde(default)]
+    pub distribution_schedule: Option<Schedule>,
+    #[serde(default)]
+    pub oracle_schedule: Option<Schedule>,
+    /// Raised funds released to the creator so far
+    #[serde(default)]
+    pub total_withdrawn: u64,
+    #[serde(default)]
+    pub currency_config: CurrencyConfig,
+    /// Revenue reported per currency, keyed by upper-case symbol
+    #[serde(default)]
+    pub revenue_by_currency: BTreeMap<String, CurrencyTotal>,
+    /// May pause and unpause any operation alongside the creator and the DAO
+    #[serde(default)]
+    pub guardian: Option<Principal>,
+    #[serde(default)]
+    pub paused: BTreeMap<Operation, PauseInfo>,
+    #[serde(default)]
+    pub reserve_config: ReserveConfig,
+    #[serde(default)]
+    pub reserve_totals: ReserveTotals,
+    /// Id the next outbox item gets; pruning never makes an id free again
+    #[serde(default)]
+    pub next_outbox_id: u64,
     pub oracle_endpoints: Vec<String>,
     pub nft_registry_canister: Option<Principal>,
     pub stream_canister: Option<Principal>,
     pub oracle_canister: Option<Principal>,
-    pub backers: HashMap<Principal, BackerInfo>,
-    pub revenue_history: Vec<RevenueUpdate>,
+    pub ledger_canister: Option<Principal>,
+    pub factory_canister: Principal,
+    pub dao_canister: Option<Principal>,
     pub created_at: u64,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct BackerInfo {
     pub amount_invested: u64,
-    pub nft_token_id: Option<u64>,
-    pub share_percentage: f64,
+    /// Fraction of the funding goal, scaled by `shares::SHARE_SCALE`
+    #[serde(default)]
+    pub share: u128,
     pub total_claimed: u64,
     pub investment_timestamp: u64,
+    pub refunded: bool,
+    pub refund_block_index: Option<u64>,
+    #[serde(default)]
+    pub lots: Vec<InvestmentLot>,
+}
+
+/// A single deposit by a backer. Each lot can be minted as its own position NFT, after
+/// which its revenue goes to the NFT's current owner.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct InvestmentLot {
+    pub amount: u64,
+    #[serde(default)]
+    pub share: u128,
+    pub timestamp: u64,
+    pub ledger_block_index: u64,
+    pub nft_token_id: Option<u64>,
+    #[serde(default)]
+    pub tranche: u32,
+    /// Revenue paid out on this lot, to whoever held it at the time
+    #[serde(default)]
+    pub claimed: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum VaultStatus {
+    Funding,   // Contributions held in escrow until the goal is met
+    Funded,    // Goal reached before the deadline
+    Refunding, // Campaign failed or was cancelled; backers may claim refunds
+    Matured,   // Revenue-share term ended; new revenue no longer accrues to backers
+}
+
+/// How long backers share in revenue. Either limit ends the term; with neither set
+/// the share runs indefinitely.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RevenueTerm {
+    /// Term length in nanoseconds, counted from the moment the campaign is funded
+    pub duration: Option<u64>,
+    /// Total distributed to backers as a multiple of principal, in basis points (20000 = 2x)
+    pub revenue_cap_bps: Option<u64>,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -43,173 +184,3085 @@ pub struct RevenueUpdate {
     pub source: String,
     pub timestamp: u64,
     pub oracle_verification: bool,
+    /// Amount this entry credited to each tranche; empty if it accrued nothing to backers
+    #[serde(default)]
+    pub tranche_amounts: Vec<u64>,
+    /// Index of the entry this one corrects after a dispute
+    #[serde(default)]
+    pub adjusts: Option<u64>,
+    /// Fees taken from this entry's backer pool before the waterfall
+    #[serde(default)]
+    pub protocol_fee: u64,
+    #[serde(default)]
+    pub creator_fee: u64,
+    /// The amount as reported and the rate it was converted to `amount` at; `None`
+    /// for entries recorded before revenue carried a currency
+    #[serde(default)]
+    pub conversion: Option<Conversion>,
+    /// The reported event this entry records; `None` for corrections
+    #[serde(default)]
+    pub event: Option<RevenueEventKey>,
+}
+
+/// DAO ruling on a revenue entry. `Freeze` holds back the entry's backer credit in the
+/// reserve until it is unfrozen, reversed or adjusted, while other revenue keeps
+/// paying out; `Adjust` replaces the entry's amount.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum DisputeAction {
+    Freeze,
+    Unfreeze,
+    Reverse,
+    Adjust { corrected_amount: u64 },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RevenueDispute {
+    pub revenue_index: u64,
+    pub action: DisputeAction,
+    /// History entry recording the reversal or adjustment
+    pub correction_index: Option<u64>,
+    pub updated_at: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RevenueReporter {
+    Oracle,
+    Creator,
+    Dao,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PayoutBatch {
+    pub payouts: Vec<(Principal, u64)>,
+    pub next_cursor: Option<Principal>,
+    pub complete: bool,
+    /// Outbox item carrying the payouts, if there were any
+    pub outbox_item: Option<u64>,
+    /// False while the stream call is unconfirmed and queued for retry
+    pub applied: bool,
+    /// Position NFTs the registry returned no owner for; paid on a later pass
+    pub unresolved_positions: Vec<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PositionInfo {
+    pub token_id: u64,
+    pub investor: Principal,
+    pub lot_index: u32,
+    pub lot: InvestmentLot,
+    pub claimable: u64,
+}
+
+/// A queued position mint from before schema 7, when mints moved to the outbox.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PendingMint {
+    pub backer: Principal,
+    pub lot_index: u32,
+    pub attempts: u32,
+    pub last_error: String,
+    pub queued_at: u64,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct InvestmentResult {
     pub success: bool,
+    pub lot_index: Option<u32>,
     pub nft_token_id: Option<u64>,
-    pub share_percentage: f64,
+    pub share: u128,
+    pub rejection: Option<InvestmentRejection>,
     pub message: String,
 }
 
+const MAX_PAYOUT_BATCH: u32 = 500;
+const MAX_OUTBOX_BATCH: u32 = 50;
+const MAX_JOURNAL_PAGE: u64 = 1_000;
+/// Payout batches the distribution timer runs per tick; the cursor carries over
+const MAX_BATCHES_PER_RUN: u32 = 10;
+
 thread_local! {
     static MEMORY_MANAGER: MemoryManager<DefaultMemoryImpl> = MemoryManager::init(DefaultMemoryImpl::default());
     
-    static VAULT_STATE: std::cell::RefCell<Option<VaultState>> = std::cell::RefCell::new(None);
+    static VAULT_STATE: RefCell<Option<VaultState>> = const { RefCell::new(None) };
+    
+    static PERSISTED_STATE: RefCell<StableCell<PersistedState, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.get(storage::CONFIG_MEMORY_ID)),
+            PersistedState::default(),
+        ).expect("Failed to initialize persisted state")
+    );
+    
+    static BACKERS: RefCell<StableBTreeMap<Principal, BackerInfo, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.get(storage::BACKERS_MEMORY_ID)))
+    );
+    
+    static REVENUE_HISTORY: RefCell<StableLog<RevenueUpdate, Memory, Memory>> = RefCell::new(
+        StableLog::init(
+            MEMORY_MANAGER.with(|m| m.get(storage::REVENUE_INDEX_MEMORY_ID)),
+            MEMORY_MANAGER.with(|m| m.get(storage::REVENUE_DATA_MEMORY_ID)),
+        ).expect("Failed to initialize revenue history")
+    );
+    
+    static REVENUE_DISPUTES: RefCell<StableBTreeMap<u64, RevenueDispute, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.get(storage::DISPUTES_MEMORY_ID)))
+    );
+    
+    static PENDING_MINTS: RefCell<StableBTreeMap<(Principal, u32), PendingMint, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.get(storage::PENDING_MINTS_MEMORY_ID)))
+    );
+    
+    static JOURNAL: RefCell<StableLog<JournalEntry, Memory, Memory>> = RefCell::new(
+        StableLog::init(
+            MEMORY_MANAGER.with(|m| m.get(storage::JOURNAL_INDEX_MEMORY_ID)),
+            MEMORY_MANAGER.with(|m| m.get(storage::JOURNAL_DATA_MEMORY_ID)),
+        ).expect("Failed to initialize journal")
+    );
+    
+    // (milestone index, backer) -> approve
+    static MILESTONE_VOTES: RefCell<StableBTreeMap<(u32, Principal), bool, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.get(storage::MILESTONE_VOTES_MEMORY_ID)))
+    );
+    
+    // (amount invested, backer), for paging backers from the largest position down
+    static BACKERS_BY_AMOUNT: RefCell<StableBTreeMap<(u64, Principal), (), Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.get(storage::BACKERS_BY_AMOUNT_MEMORY_ID)))
+    );
+    
+    static MONTHLY_REVENUE: RefCell<StableBTreeMap<MonthKey, MonthlyRevenue, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.get(storage::MONTHLY_REVENUE_MEMORY_ID)))
+    );
+    
+    static REVENUE_EVENTS: RefCell<StableBTreeMap<RevenueEventKey, RecordedEvent, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.get(storage::REVENUE_EVENTS_MEMORY_ID)))
+    );
+    
+    // Latest cumulative snapshot per platform and period
+    static REVENUE_SNAPSHOTS: RefCell<StableBTreeMap<SnapshotKey, RevenueAmount, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.get(storage::REVENUE_SNAPSHOTS_MEMORY_ID)))
+    );
+    
+    // Month (`YYYYMM`) -> what was recorded in it
+    static PERIOD_SNAPSHOTS: RefCell<StableBTreeMap<u32, PeriodSnapshot, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.get(storage::PERIOD_SNAPSHOTS_MEMORY_ID)))
+    );
+    
+    // Revenue entry index -> what it still holds in the reserve
+    static RESERVE_HOLDS: RefCell<StableBTreeMap<u64, ReserveHold, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.get(storage::RESERVE_HOLDS_MEMORY_ID)))
+    );
+    
+    static PAUSE_HISTORY: RefCell<StableLog<PauseEvent, Memory, Memory>> = RefCell::new(
+        StableLog::init(
+            MEMORY_MANAGER.with(|m| m.get(storage::PAUSE_HISTORY_INDEX_MEMORY_ID)),
+            MEMORY_MANAGER.with(|m| m.get(storage::PAUSE_HISTORY_DATA_MEMORY_ID)),
+        ).expect("Failed to initialize pause history")
+    );
+    
+    static OUTBOX: RefCell<StableBTreeMap<u64, OutboxItem, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.get(storage::OUTBOX_MEMORY_ID)))
+    );
+    
+    // Idempotency key -> latest outbox item carrying it
+    static OUTBOX_KEYS: RefCell<StableBTreeMap<String, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.get(storage::OUTBOX_KEYS_MEMORY_ID)))
+    );
+    
+    // (next attempt, id) of every pending outbox item, soonest first
+    static OUTBOX_PENDING: RefCell<StableBTreeMap<(u64, u64), (), Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.get(storage::OUTBOX_PENDING_MEMORY_ID)))
+    );
+    
+    // (completed at, id) of applied and cancelled items not yet pruned, oldest first
+    static OUTBOX_COMPLETED: RefCell<StableBTreeMap<(u64, u64), (), Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.get(storage::OUTBOX_COMPLETED_MEMORY_ID)))
+    );
+    
+    // (position NFT token id, since) -> owner of record after the investor
+    static POSITION_OWNERS: RefCell<StableBTreeMap<(u64, u64), OwnerRecord, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.get(storage::POSITION_OWNERS_MEMORY_ID)))
+    );
+    
+    // Position NFT token id -> (original investor, lot index)
+    static POSITIONS: RefCell<StableBTreeMap<u64, (Principal, u32), Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.get(storage::POSITIONS_MEMORY_ID)))
+    );
+    
+    // Principal -> allowlisted or denylisted for investing
+    static ACCESS_LIST: RefCell<StableBTreeMap<Principal, AccessLevel, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.get(storage::ACCESS_LIST_MEMORY_ID)))
+    );
+    
+    // Timers armed for each scheduled job; rebuilt from the persisted schedules on upgrade
+    static TIMERS: RefCell<BTreeMap<ScheduledJob, ic_cdk_timers::TimerId>> = const { RefCell::new(BTreeMap::new()) };
+    
+    // One-shot timer for the next outbox retry, with the time it fires at
+    static OUTBOX_TIMER: RefCell<Option<(u64, ic_cdk_timers::TimerId)>> = const { RefCell::new(None) };
+    
+    // One-shot timer for the next reserve release, with the time it fires at
+    static RESERVE_TIMER: RefCell<Option<(u64, ic_cdk_timers::TimerId)>> = const { RefCell::new(None) };
+    
+    // One-shot timer for the end of the revenue-share term
+    static MATURITY_TIMER: RefCell<Option<ic_cdk_timers::TimerId>> = const { RefCell::new(None) };
+}
+
+#[init]
+fn init(campaign_id: u64, metadata: CampaignMetadata) {
+    let milestones = milestones::build(metadata.milestones).unwrap_or_else(|e| ic_cdk::trap(e));
+    
+    let vault_state = VaultState {
+        campaign_id,
+        creator: metadata.creator,
+        title: metadata.title,
+        funding_goal: metadata.funding_goal,
+        current_funding: 0,
+        funding_deadline: metadata.funding_deadline,
+        status: VaultStatus::Funding,
+        total_refunded: 0,
+        revenue_share_percentage: metadata.revenue_share_percentage,
+        total_revenue: 0,
+        waterfall: WaterfallConfig::single_tranche(metadata.funding_goal),
+        tranches: vec![TrancheState::default()],
+        waterfall_revenue: 0,
+        total_distributable: 0,
+        total_claimed: 0,
+        term: RevenueTerm::default(),
+        term_start: None,
+        matured_at: None,
+        maturity_notified: false,
+        payout_cursor: None,
+        investment_rules: InvestmentRules::default(),
+        milestones,
+        protocol_fees: ProtocolFees::default(),
+        creator_fee_bps: 0,
+        fee_totals: FeeTotals::default(),
+        journal_balances: BTreeMap::new(),
+        distribution_schedule: None,
+        oracle_schedule: None,
+        total_withdrawn: 0,
+        currency_config: CurrencyConfig::default(),
+        revenue_by_currency: BTreeMap::new(),
+        guardian: None,
+        paused: BTreeMap::new(),
+        reserve_config: ReserveConfig::default(),
+        reserve_totals: ReserveTotals::default(),
+        next_outbox_id: 0,
+        oracle_endpoints: metadata.oracle_endpoints,
+        nft_registry_canister: None,
+        stream_canister: None,
+        oracle_canister: metadata.oracle_canister,
+        ledger_canister: None,
+        factory_canister: ic_cdk::api::msg_caller(),
+        dao_canister: metadata.dao_canister,
+        created_at: ic_cdk::api::time(),
+    };
+    
+    VAULT_STATE.with(|state| {
+        *state.borrow_mut() = Some(vault_state);
+    });
+    
+    ic_cdk::println!("Vault initialized for campaign {}", campaign_id);
+}
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    let persisted = PersistedState {
+        schema_version: SCHEMA_VERSION,
+        state: VAULT_STATE.with(|state_ref| state_ref.borrow().clone()),
+    };
+    
+    PERSISTED_STATE.with(|cell| {
+        cell.borrow_mut().set(persisted).expect("Failed to persist vault state");
+    });
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    let persisted = PERSISTED_STATE.with(|cell| cell.borrow().get().clone());
+    
+    if persisted.schema_version > SCHEMA_VERSION {
+        ic_cdk::trap(format!(
+            "Cannot downgrade vault from schema {} to {}",
+            persisted.schema_version, SCHEMA_VERSION
+        ));
+    }
+    
+    let mut persisted_state = persisted.state;
+    
+    // Runs first: every later backer migration works on lots
+    if persisted.schema_version < 10 {
+        if let Some(ref state) = persisted_state {
+            migrate_single_positions(state.funding_goal);
+        }
+    }
+    
+    if persisted.schema_version < 2 {
+        if let Some(ref state) = persisted_state {
+            migrate_float_shares(state.funding_goal);
+        }
+    }
+    
+    if persisted.schema_version < 3 {
+        if let Some(ref mut state) = persisted_state {
+            migrate_single_accumulator(state);
+        }
+    }
+    
+    if persisted.schema_version < 4 {
+        if let Some(ref state) = persisted_state {
+            migrate_lot_claims(state);
+        }
+    }
+    
+    // Schema 4 vaults had no milestones; all funds are released by a single one
+    if persisted.schema_version < 5 {
+        if let Some(ref mut state) = persisted_state {
+            state.milestones = vec![Milestone::from(milestones::single_release())];
+        }
+    }
+    
+    if persisted.schema_version < 6 {
+        if let Some(ref mut state) = persisted_state {
+            migrate_opening_balances(state);
+        }
+    }
+    
+    if persisted.schema_version < 7 {
+        migrate_pending_mints();
+    }
+    
+    if persisted.schema_version < 8 {
+        migrate_history_indexes();
+    }
+    
+    if persisted.schema_version < 9 {
+        if let Some(ref state) = persisted_state {
+            migrate_period_snapshots(state);
+        }
+    }
+    
+    if persisted.schema_version < 11 {
+        if let Some(ref mut state) = persisted_state {
+            migrate_outbox_indexes(state);
+        }
+    }
+    
+    VAULT_STATE.with(|state_ref| {
+        *state_ref.borrow_mut() = persisted_state;
+    });
+    
+    arm_timer(ScheduledJob::Distribution);
+    arm_timer(ScheduledJob::OraclePull);
+    schedule_outbox_retry();
+    schedule_reserve_release();
+    schedule_maturity();
+    
+    ic_cdk::println!("Vault upgraded from schema {} to {}", persisted.schema_version, SCHEMA_VERSION);
+}
+
+/// Schema 1 stored `f64` share percentages; recompute fixed-point shares from amounts.
+fn migrate_float_shares(funding_goal: u64) {
+    BACKERS.with(|backers| {
+        let mut backers = backers.borrow_mut();
+        let entries: Vec<(Principal, BackerInfo)> = backers.iter().collect();
+        
+        for (backer, mut info) in entries {
+            for lot in info.lots.iter_mut() {
+                lot.share = shares::share_of(lot.amount, funding_goal);
+            }
+            info.share = shares::share_of(info.amount_invested, funding_goal);
+            backers.insert(backer, info);
+        }
+    });
+}
+
+/// Schema 1 vaults written before lots stored one position per backer, which later
+/// layouts no longer decode; turn each into a single lot with its amount, ledger block
+/// and position NFT.
+fn migrate_single_positions(funding_goal: u64) {
+    let legacy: Vec<(Principal, LegacyBackerInfo)> = {
+        let records: StableBTreeMap<Principal, LegacyBackerInfo, Memory> =
+            StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.get(storage::BACKERS_MEMORY_ID)));
+        records.iter()
+            .filter(|(_, record)| record.lots.is_empty() && record.ledger_block_index.is_some())
+            .collect()
+    };
+    
+    for (backer, record) in legacy {
+        update_backer(&backer, |info| {
+            info.lots.push(InvestmentLot {
+                amount: record.amount_invested,
+                share: shares::share_of(record.amount_invested, funding_goal),
+                timestamp: record.investment_timestamp,
+                ledger_block_index: record.ledger_block_index.unwrap_or_default(),
+                nft_token_id: record.nft_token_id,
+                tranche: 0,
+                claimed: 0,
+            });
+        });
+        if let Some(token_id) = record.nft_token_id {
+            POSITIONS.with(|positions| positions.borrow_mut().insert(token_id, (backer, 0)));
+        }
+    }
+}
+
+/// Schema 2 kept one vault-wide accumulator; fold it into a single default tranche.
+fn migrate_single_accumulator(state: &mut VaultState) {
+    let raised = state.current_funding;
+    state.waterfall = WaterfallConfig::single_tranche(state.funding_goal);
+    state.tranches = vec![TrancheState {
+        raised,
+        received: state.total_distributable,
+        revenue_per_unit: if raised == 0 {
+            0
+        } else {
+            state.total_distributable as u128 * shares::SHARE_SCALE / raised as u128
+        },
+        revenue_per_unit_carry: 0,
+    }];
+    state.waterfall_revenue = if state.status == VaultStatus::Funded { state.total_revenue } else { 0 };
+}
+
+/// Schema 3 tracked claims per backer; spread each backer's total over their lots in
+/// order, and index minted lots by token id.
+fn migrate_lot_claims(state: &VaultState) {
+    let tranche_rates: Vec<u128> = state.tranches.iter().map(|t| t.revenue_per_unit).collect();
+    
+    BACKERS.with(|backers| {
+        let mut backers = backers.borrow_mut();
+        let entries: Vec<(Principal, BackerInfo)> = backers.iter().collect();
+        
+        for (backer, mut info) in entries {
+            let mut remaining = info.total_claimed;
+            for (lot_index, lot) in info.lots.iter_mut().enumerate() {
+                let rate = tranche_rates.get(lot.tranche as usize).copied().unwrap_or(0);
+                lot.claimed = shares::accrued(lot.amount, rate).min(remaining);
+                remaining -= lot.claimed;
+                
+                if let Some(token_id) = lot.nft_token_id {
+                    POSITIONS.with(|positions| positions.borrow_mut().insert(token_id, (backer, lot_index as u32)));
+                }
+            }
+            if let Some(last) = info.lots.last_mut() {
+                last.claimed += remaining;
+            }
+            backers.insert(backer, info);
+        }
+    });
+}
+
+/// Schema 5 had no journal; open it with the balances implied by the vault's counters.
+fn migrate_opening_balances(state: &mut VaultState) {
+    let capital = state.current_funding.saturating_sub(state.total_withdrawn + state.total_refunded);
+    let escrow_owed = state.fee_totals.escrow_owed();
+    let revenue_owed = state.fee_totals.revenue_owed();
+    let streamed = (state.total_claimed - state.fee_totals.payout) + state.fee_totals.revenue_collected;
+    
+    let entry = JournalEntry::new(JournalKind::Opening, "schema 6 migration".to_string(), ic_cdk::api::time())
+        .debit(JournalAccount::Escrow, capital + escrow_owed)
+        .credit(JournalAccount::BackerCapital, capital)
+        .debit(JournalAccount::ReportedRevenue, state.total_revenue)
+        .credit(JournalAccount::BackerPayable, state.total_distributable.saturating_sub(state.total_claimed))
+        .credit(JournalAccount::TreasuryPayable, escrow_owed + revenue_owed)
+        .credit(JournalAccount::StreamedPayouts, streamed);
+    
+    // Whatever the counters leave unexplained is revenue the creator kept
+    let unbalanced: i128 = entry.lines.iter().map(|l| l.credit as i128 - l.debit as i128).sum();
+    post_journal(state, entry.signed(JournalAccount::CreatorRevenue, unbalanced));
+}
+
+/// Schema 7 had no backer or monthly revenue indexes; build them from the stored records.
+fn migrate_history_indexes() {
+    BACKERS.with(|backers| {
+        BACKERS_BY_AMOUNT.with(|index| {
+            let mut index = index.borrow_mut();
+            for (backer, info) in backers.borrow().iter() {
+                index.insert((info.amount_invested, backer), ());
+            }
+        })
+    });
+    
+    let len = REVENUE_HISTORY.with(|history| history.borrow().len());
+    for revenue_index in 0..len {
+        if let Some(entry) = REVENUE_HISTORY.with(|history| history.borrow().get(revenue_index)) {
+            record_monthly_revenue(&entry);
+        }
+    }
+}
+
+/// Schema 8 kept no period snapshots; rebuild them by replaying the revenue history
+/// through fresh tranche accumulators, then the payouts in the journal.
+fn migrate_period_snapshots(state: &VaultState) {
+    // (revenue per unit, carry) of each tranche as the history is replayed
+    let mut accumulators = vec![(0u128, 0u128); state.tranches.len()];
+    
+    let len = REVENUE_HISTORY.with(|history| history.borrow().len());
+    for revenue_index in 0..len {
+        let Some(entry) = REVENUE_HISTORY.with(|history| history.borrow().get(revenue_index)) else {
+            continue;
+        };
+        let original = entry.adjusts.and_then(|index| REVENUE_HISTORY.with(|history| history.borrow().get(index)));
+        
+        for (index, (tranche, (per_unit, carry))) in state.tranches.iter().zip(accumulators.iter_mut()).enumerate() {
+            if let Some(amount) = original.as_ref().and_then(|o| o.tranche_amounts.get(index)) {
+                (*per_unit, *carry) = shares::reverse(*per_unit, *carry, *amount, tranche.raised);
+            }
+            if let Some(amount) = entry.tranche_amounts.get(index) {
+                (*per_unit, *carry) = shares::accrue(*per_unit, *carry, *amount, tranche.raised);
+            }
+        }
+        let rates = accumulators.iter().map(|(per_unit, _)| *per_unit).collect();
+        snapshot_revenue(entry.timestamp, rates, &entry, original.as_ref());
+    }
+    
+    let len = JOURNAL.with(|journal| journal.borrow().len());
+    for index in 0..len {
+        let Some(entry) = JOURNAL.with(|journal| journal.borrow().get(index)) else {
+            continue;
+        };
+        if entry.kind != JournalKind::Payout {
+            continue;
+        }
+        let amount: u64 = entry.lines.iter().filter(|l| l.account == JournalAccount::BackerPayable).map(|l| l.debit).sum();
+        let fee: u64 = entry.lines.iter().filter(|l| l.account == JournalAccount::TreasuryPayable).map(|l| l.credit).sum();
+        snapshot_payout(entry.timestamp, amount, fee);
+    }
+}
+
+/// Schema 6 queued failed mints in their own map; move them into the outbox.
+fn migrate_pending_mints() {
+    let queued: Vec<PendingMint> = PENDING_MINTS.with(|pending| {
+        let mut pending = pending.borrow_mut();
+        let queued: Vec<PendingMint> = pending.iter().map(|(_, p)| p).collect();
+        for mint in &queued {
+            pending.remove(&(mint.backer, mint.lot_index));
+        }
+        queued
+    });
+    
+    for mint in queued {
+        let id = enqueue(
+            Some(mint_key(mint.backer, mint.lot_index)),
+            Effect::MintPosition { backer: mint.backer, lot_index: mint.lot_index },
+        );
+        if let Some(mut item) = OUTBOX.with(|outbox| outbox.borrow().get(&id)) {
+            item.attempts = mint.attempts;
+            item.last_error = Some(mint.last_error);
+            item.created_at = mint.queued_at;
+            store_outbox_item(item);
+        }
+    }
+}
+
+/// Schema 10 scanned the whole outbox to find items by key and due retries; index
+/// it, and count item ids on from the last one so pruning never frees an id.
+fn migrate_outbox_indexes(state: &mut VaultState) {
+    let items: Vec<OutboxItem> = OUTBOX.with(|outbox| outbox.borrow().iter().map(|(_, item)| item).collect());
+    state.next_outbox_id = items.last().map(|item| item.id + 1).unwrap_or(0);
+    for item in items {
+        store_outbox_item(item);
+    }
+}
+
+/// Invests into `tranche`, or into the first tranche with capacity left when `None`.
+/// Tickets that break the campaign's investment rules are rejected, never resized.
+#[update]
+async fn invest(amount: u64, tranche: Option<u32>) -> InvestmentResult {
+    let caller = ic_cdk::api::msg_caller();
+    let now = ic_cdk::api::time();
+    
+    // One investment per principal at a time, so the per-backer cap sees every ticket
+    let _guard = match Guard::acquire(Lock::Principal(caller)) {
+        Ok(guard) => guard,
+        Err(_) => return failed_investment(InvestmentRejection::CallInProgress),
+    };
+    
+    if let Err(OperationError::Paused { reason, .. }) = check_not_paused(Operation::Investing) {
+        return failed_investment(InvestmentRejection::Paused { reason });
+    }
+    
+    let access = ACCESS_LIST.with(|list| list.borrow().get(&caller));
+    let invested = get_backer(&caller).map(|info| info.amount_invested).unwrap_or(0);
+    
+    // Reserve the investment against the funding goal before calling the ledger,
+    // so concurrent investments cannot overshoot it while the transfer is in flight.
+    let reservation = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            let ledger = match state.ledger_canister {
+                Some(ledger) => ledger,
+                None => return Err(InvestmentRejection::LedgerNotConfigured),
+            };
+           
+            if state.status != VaultStatus::Funding || state.current_funding >= state.funding_goal {
+                return Err(InvestmentRejection::NotAcceptingInvestments);
+            }
+            
+            if now > state.funding_deadline {
+                return Err(InvestmentRejection::DeadlinePassed);
+            }
+           
+            let capacity = |i: usize| state.waterfall.tranches[i].size.saturating_sub(state.tranches[i].raised);
+            let tranche_index = match tranche {
+                Some(index) if (index as usize) < state.tranches.len() => index as usize,
+                Some(_) => return Err(InvestmentRejection::TrancheNotFound),
+                None => (0..state.tranches.len()).find(|&i| capacity(i) > 0).unwrap_or(0),
+            };
+            
+            let available = capacity(tranche_index);
+            if available == 0 {
+                return Err(InvestmentRejection::TrancheFull);
+            }
+            
+            state.investment_rules.check(access, amount, invested, available, now)?;
+            
+            let share = shares::share_of(amount, state.funding_goal);
+            
+            state.current_funding += amount;
+            state.tranches[tranche_index].raised += amount;
+            
+            Ok((ledger, share, tranche_index as u32))
+        } else {
+            Err(InvestmentRejection::VaultNotInitialized)
+        }
+    });
+    
+    let (ledger, share, tranche) = match reservation {
+        Ok(reserved) => reserved,
+        Err(rejection) => return failed_investment(rejection),
+    };
+    
+    let transfer = {
+        let memo = get_campaign_id().to_be_bytes().to_vec();
+        ledger::transfer_from(ledger, caller, amount, memo).await
+    };
+    
+    match transfer {
+        Ok(block_index) => {
+            let now = ic_cdk::api::time();
+            let lot = InvestmentLot {
+                amount,
+                share,
+                timestamp: now,
+                ledger_block_index: block_index,
+                nft_token_id: None,
+                tranche,
+                claimed: 0,
+            };
+            
+            // Merge into any existing position rather than replacing it
+            let lot_index = BACKERS.with(|backers| {
+                let mut backers = backers.borrow_mut();
+                let mut backer_info = backers.get(&caller).unwrap_or(BackerInfo {
+                    amount_invested: 0,
+                    share: 0,
+                    total_claimed: 0,
+                    investment_timestamp: now,
+                    refunded: false,
+                    refund_block_index: None,
+                    lots: Vec::new(),
+                });
+                
+                BACKERS_BY_AMOUNT.with(|index| {
+                    let mut index = index.borrow_mut();
+                    index.remove(&(backer_info.amount_invested, caller));
+                    index.insert((backer_info.amount_invested + lot.amount, caller), ());
+                });
+                
+                backer_info.amount_invested += lot.amount;
+                backer_info.share += lot.share;
+                backer_info.lots.push(lot);
+                
+                let lot_index = (backer_info.lots.len() - 1) as u32;
+                backers.insert(caller, backer_info);
+                lot_index
+            });
+            
+            VAULT_STATE.with(|state_ref| {
+                if let Some(ref mut state) = *state_ref.borrow_mut() {
+                    post_journal(state, JournalEntry::new(
+                        JournalKind::Deposit,
+                        format!("{} lot {} block {}", caller.to_text(), lot_index, block_index),
+                        now,
+                    )
+                    .debit(JournalAccount::Escrow, amount)
+                    .credit(JournalAccount::BackerCapital, amount));
+                    
+                    // Escrow is released once the goal is met
+                    if state.status == VaultStatus::Funding && state.current_funding >= state.funding_goal {
+                        state.status = VaultStatus::Funded;
+                        state.term_start = Some(now);
+                    }
+                }
+            });
+            schedule_maturity();
+            
+            // The investment stands even if minting fails; the lot is queued for retry
+            let (nft_token_id, message) = match mint_position(caller, lot_index).await {
+                Ok(token_id) => (
+                    Some(token_id),
+                    format!("Investment of {} successful (block {}, NFT {})", amount, block_index, token_id),
+                ),
+                Err(e) => (
+                    None,
+                    format!("Investment of {} successful (block {}); NFT mint pending: {}", amount, block_index, e),
+                ),
+            };
+            
+            InvestmentResult {
+                success: true,
+                lot_index: Some(lot_index),
+                nft_token_id,
+                share,
+                rejection: None,
+                message,
+            }
+        }
+        Err(e) => {
+            // Release the reservation; no tokens moved.
+            VAULT_STATE.with(|state_ref| {
+                if let Some(ref mut state) = *state_ref.borrow_mut() {
+                    state.current_funding -= amount;
+                    state.tranches[tranche as usize].raised -= amount;
+                }
+            });
+            failed_investment(InvestmentRejection::TransferFailed { message: e })
+        }
+    }
+}
+
+fn failed_investment(rejection: InvestmentRejection) -> InvestmentResult {
+    InvestmentResult {
+        success: false,
+        lot_index: None,
+        nft_token_id: None,
+        share: 0,
+        message: rejection.message(),
+        rejection: Some(rejection),
+    }
+}
+
+/// Opens the refund window. The DAO may call this at any time before the campaign
+/// matures; the creator, directly or through the factory when they cancel the
+/// campaign, only while it is still funding and no funds have been released; anyone
+/// once the deadline has passed without the goal being met.
+#[update]
+fn initiate_refund() -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    let now = ic_cdk::api::time();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if state.status == VaultStatus::Refunding {
+                return Err("Refunds already initiated".to_string());
+            }
+            
+            if state.status == VaultStatus::Matured {
+                return Err("Campaign has matured".to_string());
+            }
+            
+            // The factory only calls this when the creator cancels, so it gets the creator's rights
+            let governs = Some(caller) == state.dao_canister;
+            let creator = caller == state.creator || caller == state.factory_canister;
+            
+            if !governs && (state.status != VaultStatus::Funding || state.total_withdrawn > 0) {
+                return Err("Only the DAO can initiate refunds once the campaign is funded".to_string());
+            }
+            
+            if !governs && !creator && !funding_failed(state, now) {
+                return Err("Only creator, factory or DAO can initiate refunds before the deadline".to_string());
+            }
+            
+            state.status = VaultStatus::Refunding;
+            ic_cdk::println!("Refunds initiated for campaign {}", state.campaign_id);
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+/// Returns the caller's `amount_invested`, less the ledger fee, from escrow. Once
+/// milestone funds have been released, each backer gets their pro rata part of what
+/// is left in escrow instead.
+#[update]
+async fn claim_refund() -> Result<u64, OperationError> {
+    let caller = ic_cdk::api::msg_caller();
+    let now = ic_cdk::api::time();
+    check_not_paused(Operation::Refunds)?;
+    let _guard = Guard::acquire(Lock::Principal(caller))?;
+    
+    // Mark the position refunded before the transfer so a concurrent claim cannot pay twice.
+    let (ledger, amount) = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if funding_failed(state, now) {
+                state.status = VaultStatus::Refunding;
+            }
+            
+            if state.status != VaultStatus::Refunding {
+                return Err("Refunds are not available for this campaign".to_string());
+            }
+            
+            let ledger = state.ledger_canister.ok_or_else(|| "Ledger canister not configured".to_string())?;
+            Ok((ledger, state.current_funding, state.total_withdrawn))
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+    .and_then(|(ledger, raised, withdrawn)| {
+        update_backer(&caller, |info| {
+            if info.refunded {
+                return Err("Refund already claimed".to_string());
+            }
+            info.refunded = true;
+            
+            let amount = if withdrawn == 0 {
+                info.amount_invested
+            } else {
+                (info.amount_invested as u128 * (raised - withdrawn) as u128 / raised as u128) as u64
+            };
+            Ok((ledger, amount))
+        })
+        .unwrap_or_else(|| Err("Backer not found".to_string()))
+    })?;
+    
+    let result = match ledger::fee(ledger).await {
+        Ok(fee) if amount > fee => {
+            let memo = get_campaign_id().to_be_bytes().to_vec();
+            ledger::transfer(ledger, caller, amount - fee, memo).await
+        }
+        Ok(_) => Err("Investment does not cover the ledger fee".to_string()),
+        Err(e) => Err(e),
+    };
+    
+    update_backer(&caller, |info| match result {
+        Ok(block_index) => info.refund_block_index = Some(block_index),
+        Err(_) => info.refunded = false,
+    });
+    
+    if let Ok(block_index) = result {
+        VAULT_STATE.with(|state_ref| {
+            if let Some(ref mut state) = *state_ref.borrow_mut() {
+                state.total_refunded += amount;
+                post_journal(state, JournalEntry::new(
+                    JournalKind::Refund,
+                    format!("{} block {}", caller.to_text(), block_index),
+                    ic_cdk::api::time(),
+                )
+                .debit(JournalAccount::BackerCapital, amount)
+                .credit(JournalAccount::Escrow, amount));
+            }
+        });
+    }
+    
+    result.map(|_| amount).map_err(OperationError::from)
+}
+
+fn funding_failed(state: &VaultState, now: u64) -> bool {
+    state.status == VaultStatus::Funding
+        && now > state.funding_deadline
+        && state.current_funding < state.funding_goal
+}
+
+/// Submits evidence for milestone `index` and opens its backer review. Milestones are
+/// released in order, and a rejected milestone can be resubmitted with new evidence.
+#[update]
+fn submit_milestone_evidence(index: u32, evidence: String) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    let now = ic_cdk::api::time();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if state.creator != caller {
+                return Err("Only creator can submit milestone evidence".to_string());
+            }
+            
+            if state.status != VaultStatus::Funded && state.status != VaultStatus::Matured {
+                return Err("Milestones can only be submitted once the campaign is funded".to_string());
+            }
+            
+            let index = index as usize;
+            if index >= state.milestones.len() {
+                return Err("Milestone not found".to_string());
+            }
+            
+            if state.milestones[..index].iter().any(|m| m.status != MilestoneStatus::Released) {
+                return Err("Earlier milestones must be released first".to_string());
+            }
+            
+            let milestone = &mut state.milestones[index];
+            if milestone.status != MilestoneStatus::Pending && milestone.status != MilestoneStatus::Rejected {
+                return Err("Milestone is not awaiting evidence".to_string());
+            }
+            
+            milestone.status = MilestoneStatus::UnderReview;
+            milestone.evidence = Some(evidence);
+            milestone.review_ends_at = Some(now + milestones::REVIEW_PERIOD);
+            milestone.votes_for = 0;
+            milestone.votes_against = 0;
+            
+            clear_milestone_votes(index as u32);
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+/// Records the caller's vote on a milestone under review, weighted by what they invested.
+#[update]
+fn vote_on_milestone(index: u32, approve: bool) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    let now = ic_cdk::api::time();
+    
+    let weight = get_backer(&caller)
+        .filter(|info| !info.refunded)
+        .map(|info| info.amount_invested)
+        .unwrap_or(0);
+    if weight == 0 {
+        return Err("Only backers can vote on milestones".to_string());
+    }
+    
+    if MILESTONE_VOTES.with(|votes| votes.borrow().contains_key(&(index, caller))) {
+        return Err("Already voted on this milestone".to_string());
+    }
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            let total_weight = state.current_funding;
+            let milestone = state.milestones.get_mut(index as usize)
+                .ok_or_else(|| "Milestone not found".to_string())?;
+            
+            milestone.settle(total_weight, now);
+            if milestone.status != MilestoneStatus::UnderReview {
+                return Err("Milestone is not under review".to_string());
+            }
+            
+            if approve {
+                milestone.votes_for += weight;
+            } else {
+                milestone.votes_against += weight;
+            }
+            milestone.settle(total_weight, now);
+            
+            MILESTONE_VOTES.with(|votes| votes.borrow_mut().insert((index, caller), approve));
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+/// Transfers an approved milestone's share of the raised funds, less the protocol raise
+/// fee and the ledger fee, from escrow to the creator. The raise fee stays in escrow
+/// until `collect_fees` sends it to the treasury.
+#[update]
+async fn withdraw_funds(index: u32) -> Result<u64, String> {
+    let caller = ic_cdk::api::msg_caller();
+    let now = ic_cdk::api::time();
+    
+    // Mark the milestone released before the transfer so it cannot be paid twice
+    let (ledger, amount, raise_fee) = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if state.creator != caller {
+                return Err("Only creator can withdraw funds".to_string());
+            }
+            
+            if state.status != VaultStatus::Funded && state.status != VaultStatus::Matured {
+                return Err("Funds are still in escrow".to_string());
+            }
+            
+            let ledger = state.ledger_canister.ok_or_else(|| "Ledger canister not configured".to_string())?;
+            let amounts = milestones::release_amounts(&state.milestones, state.current_funding);
+            let total_weight = state.current_funding;
+            
+            let milestone = state.milestones.get_mut(index as usize)
+                .ok_or_else(|| "Milestone not found".to_string())?;
+            milestone.settle(total_weight, now);
+            let amount = amounts[index as usize];
+            milestone.release(amount)?;
+            let raise_fee = shares::apply_bps(amount, state.protocol_fees.raise_fee_bps);
+            state.total_withdrawn += amount;
+            state.fee_totals.raise += raise_fee;
+            
+            Ok((ledger, amount, raise_fee))
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    let net = amount - raise_fee;
+    let result = match ledger::fee(ledger).await {
+        Ok(fee) if net > fee => {
+            let memo = get_campaign_id().to_be_bytes().to_vec();
+            ledger::transfer(ledger, caller, net - fee, memo).await
+        }
+        Ok(_) => Err("Milestone release does not cover the ledger fee".to_string()),
+        Err(e) => Err(e),
+    };
+    
+    VAULT_STATE.with(|state_ref| {
+        if let Some(ref mut state) = *state_ref.borrow_mut() {
+            let milestone = &mut state.milestones[index as usize];
+            match result {
+                Ok(block_index) => {
+                    milestone.release_block_index = Some(block_index);
+                    post_journal(state, JournalEntry::new(
+                        JournalKind::Release,
+                        format!("milestone {} block {}", index, block_index),
+                        ic_cdk::api::time(),
+                    )
+                    .debit(JournalAccount::BackerCapital, amount)
+                    .credit(JournalAccount::Escrow, net)
+                    .credit(JournalAccount::TreasuryPayable, raise_fee));
+                }
+                Err(_) => {
+                    milestone.status = MilestoneStatus::Approved;
+                    milestone.released_amount = 0;
+                    state.total_withdrawn -= amount;
+                    state.fee_totals.raise -= raise_fee;
+                }
+            }
+        }
+    });
+    
+    result.map(|_| net)
+}
+
+fn clear_milestone_votes(index: u32) {
+    MILESTONE_VOTES.with(|votes| {
+        let mut votes = votes.borrow_mut();
+        let keys: Vec<(u32, Principal)> = votes
+            .range((index, Principal::management_canister())..)
+            .map(|(key, _)| key)
+            .take_while(|(milestone, _)| *milestone == index)
+            .collect();
+        
+        for key in keys {
+            votes.remove(&key);
+        }
+    });
+}
+
+/// Mints the position NFT for one lot, e.g. after the registry was configured late.
+#[update]
+async fn mint_nft_for_backer(backer: Principal, lot_index: u32) -> Result<u64, String> {
+    mint_position(backer, lot_index).await
+}
+
+/// Mints the NFT for `backer`'s lot `lot_index` through the outbox. A mint the
+/// registry does not confirm stays queued there and is retried.
+async fn mint_position(backer: Principal, lot_index: u32) -> Result<u64, String> {
+    let _guard = Guard::acquire(Lock::Mint(backer, lot_index))?;
+    
+    let lot = get_backer(&backer)
+        .ok_or_else(|| "Backer not found".to_string())?
+        .lots
+        .get(lot_index as usize)
+        .cloned()
+        .ok_or_else(|| "Investment lot not found".to_string())?;
+    
+    if lot.nft_token_id.is_some() {
+        return Err("NFT already minted for this investment".to_string());
+    }
+    
+    // Reuse the lot's queued mint if there is one, so it keeps a single key
+    let key = mint_key(backer, lot_index);
+    let queued = pending_outbox_item(&key);
+    let id = queued.unwrap_or_else(|| enqueue(Some(key), Effect::MintPosition { backer, lot_index }));
+    
+    process_outbox_item(id).await?;
+    
+    get_backer(&backer)
+        .and_then(|info| info.lots.get(lot_index as usize).and_then(|lot| lot.nft_token_id))
+        .ok_or_else(|| "NFT mint did not complete".to_string())
+}
+
+/// The registry keys mints by this, so one lot can never hold two position NFTs.
+fn mint_key(backer: Principal, lot_index: u32) -> String {
+    format!("mint:{}:{}", backer.to_text(), lot_index)
+}
+
+/// Calls `method` on `canister`, waiting for the reply however long it takes, and
+/// decodes its single return value.
+async fn call<A, R>(canister: Principal, method: &str, args: A) -> Result<R, ic_cdk::call::Error>
+where
+    A: candid::utils::ArgumentEncoder,
+    R: CandidType + for<'de> Deserialize<'de>,
+{
+    let response = Call::unbounded_wait(canister, method).with_args(&args).await?;
+    Ok(response.candid()?)
+}
+
+async fn call_mint(nft_registry: Principal, backer: Principal, lot: &InvestmentLot, key: String) -> Result<u64, Failure> {
+    let campaign_id = get_campaign_id();
+    let metadata = format!(
+        "{{\"campaign_id\":{},\"investment\":{},\"share\":{},\"tranche\":{}}}",
+        campaign_id,
+        lot.amount,
+        lot.share,
+        lot.tranche
+    );
+    
+    let result: Result<Result<u64, String>, _> = call(
+        nft_registry,
+        "mint",
+        (backer, campaign_id, ic_cdk::api::canister_self(), lot.amount, lot.share, metadata, Some(key)),
+    ).await;
+    
+    match result {
+        Ok(Ok(token_id)) => Ok(token_id),
+        Ok(Err(e)) => Err(Failure::Rejected(e)),
+        Err(e) => Err(Failure::Uncertain(format!("Failed to call NFT registry: {:?}", e))),
+    }
+}
+
+/// Records a revenue report. Each event is recorded once; a cumulative snapshot only
+/// counts its growth over the period's previous snapshot. New revenue is converted to
+/// the payout token at the rate source's current rate before it accrues to backers.
+#[update]
+async fn update_revenue(report: RevenueReport) -> Result<(), OperationError> {
+    let caller = ic_cdk::api::msg_caller();
+    
+    check_not_paused(Operation::RevenueIngestion)?;
+    report.validate()?;
+    let report = RevenueReport {
+        revenue: RevenueAmount { currency: report.revenue.currency.to_uppercase(), ..report.revenue },
+        ..report
+    };
+    let event_key = report.event_key();
+    let snapshot_key = report.snapshot_key();
+    
+    let (verified, rate_source, payout_token) = VAULT_STATE.with(|state_ref| {
+        let state_opt = state_ref.borrow();
+        if let Some(ref state) = *state_opt {
+            // Oracle and DAO reports count as verified; the creator may only post manual entries
+            let verified = match revenue_reporter(state, caller) {
+                Some(RevenueReporter::Oracle) | Some(RevenueReporter::Dao) => true,
+                Some(RevenueReporter::Creator) => false,
+                None => return Err("Only the oracle, creator or DAO can post revenue".to_string()),
+            };
+            
+            check_currency_decimals(state, &report.revenue)?;
+            let payout_token = state.currency_config.payout_token.clone()
+                .ok_or_else(|| "Payout token not configured".to_string())?;
+            
+            Ok((verified, state.currency_config.rate_source, payout_token))
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    check_new_event(&event_key)?;
+    let previous = REVENUE_SNAPSHOTS.with(|snapshots| snapshots.borrow().get(&snapshot_key));
+    let Some(new_amount) = report.new_revenue(previous.as_ref())? else {
+        record_event(&report, event_key, None);
+        return Ok(());
+    };
+    
+    let new_revenue = RevenueAmount { amount: new_amount, ..report.revenue.clone() };
+    let (amount, conversion) = rates::to_payout_token(rate_source.map(XrcSource).as_ref(), &payout_token, new_revenue, ic_cdk::api::time()).await?;
+    let now = ic_cdk::api::time();
+    
+    // Another report or a pause may have landed while the rate was fetched
+    check_not_paused(Operation::RevenueIngestion)?;
+    check_new_event(&event_key)?;
+    if REVENUE_SNAPSHOTS.with(|snapshots| snapshots.borrow().get(&snapshot_key)) != previous {
+        return Err(format!("Snapshot for {} {} changed while converting; report again", report.platform, report.period).into());
+    }
+    
+    let matured = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            check_currency_decimals(state, &conversion.reported)?;
+            
+            ic_cdk::println!(
+                "Revenue updated: {} {} ({} payout units at rate {}e-{}) from {} {} (verified: {})",
+                conversion.reported.amount, conversion.reported.currency, amount,
+                conversion.rate, conversion.rate_decimals, report.platform, report.external_id, verified
+            );
+            
+            state.total_revenue += amount;
+            count_currency_revenue(state, &conversion.reported, amount);
+            
+            // Revenue after the term ends is recorded but no longer accrues to backers
+            let expired = check_maturity(state, now);
+            let accrual = accrue_backer_revenue(state, amount);
+            let (credited, reserved) = (accrual.credited(), accrual.reserved());
+            
+            let entry = RevenueUpdate {
+                amount,
+                source: report.platform.clone(),
+                timestamp: now,
+                oracle_verification: verified,
+                tranche_amounts: accrual.tranche_amounts,
+                adjusts: None,
+                protocol_fee: accrual.protocol_fee,
+                creator_fee: accrual.creator_fee,
+                conversion: Some(conversion),
+                event: Some(event_key.clone()),
+            };
+            let revenue_index = record_revenue(&entry);
+            snapshot_revenue(now, tranche_rates(state), &entry, None);
+            post_journal(state, journal::revenue_accrual(
+                format!("revenue {}", revenue_index),
+                now,
+                amount,
+                credited,
+                reserved,
+                accrual.protocol_fee,
+            ));
+            hold_reserve(state, revenue_index, accrual.held, now);
+            record_event(&report, event_key, Some(revenue_index));
+            
+            Ok(expired || check_maturity(state, now))
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    schedule_reserve_release();
+    
+    if matured {
+        ic_cdk::futures::spawn(async {
+            if let Err(e) = notify_maturity().await {
+                ic_cdk::println!("Failed to notify maturity: {}", e);
+            }
+        });
+    }
+    
+    Ok(())
+}
+
+fn check_new_event(event_key: &RevenueEventKey) -> Result<(), String> {
+    match REVENUE_EVENTS.with(|events| events.borrow().get(event_key)) {
+        Some(recorded) => Err(format!(
+            "Revenue event {}/{}/{} was already recorded{}",
+            event_key.platform,
+            event_key.period,
+            event_key.external_id,
+            recorded.revenue_index.map(|index| format!(" as entry {}", index)).unwrap_or_default()
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Marks the report's event as recorded and moves its period's snapshot forward.
+fn record_event(report: &RevenueReport, event_key: RevenueEventKey, revenue_index: Option<u64>) {
+    REVENUE_EVENTS.with(|events| {
+        events.borrow_mut().insert(event_key, RecordedEvent {
+            revenue_index,
+            recorded_at: ic_cdk::api::time(),
+        });
+    });
+    
+    if report.kind == ingestion::ReportKind::CumulativeSnapshot {
+        REVENUE_SNAPSHOTS.with(|snapshots| {
+            snapshots.borrow_mut().insert(report.snapshot_key(), report.revenue.clone());
+        });
+    }
+}
+
+/// A currency keeps the decimals it was first reported with, so its totals add up.
+fn check_currency_decimals(state: &VaultState, revenue: &RevenueAmount) -> Result<(), String> {
+    match state.revenue_by_currency.get(&revenue.currency) {
+        Some(total) if total.decimals != revenue.decimals => Err(format!(
+            "Revenue in {} is reported with {} decimals",
+            revenue.currency, total.decimals
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn count_currency_revenue(state: &mut VaultState, reported: &RevenueAmount, converted: u64) {
+    let total = state.revenue_by_currency.entry(reported.currency.clone()).or_insert_with(|| CurrencyTotal {
+        decimals: reported.decimals,
+        ..CurrencyTotal::default()
+    });
+    total.reported += reported.amount;
+    total.converted += converted;
+}
+
+/// Appends to the revenue history and returns the entry's index. Traps on failure so
+/// the state changes made alongside the entry are rolled back with it.
+fn record_revenue(revenue_update: &RevenueUpdate) -> u64 {
+    record_monthly_revenue(revenue_update);
+    REVENUE_HISTORY.with(|history| {
+        history.borrow().append(revenue_update)
+            .unwrap_or_else(|e| ic_cdk::trap(format!("Failed to record revenue: {:?}", e)))
+    })
+}
+
+/// Counts `entry` in its month's total for its source. A correction replaces the
+/// entry it corrects, in that entry's month.
+fn record_monthly_revenue(entry: &RevenueUpdate) {
+    let original = entry.adjusts.and_then(|index| REVENUE_HISTORY.with(|history| history.borrow().get(index)));
+    let key = MonthKey {
+        month: history::month_of(original.as_ref().unwrap_or(entry).timestamp),
+        source: entry.source.clone(),
+    };
+    
+    MONTHLY_REVENUE.with(|monthly| {
+        let mut monthly = monthly.borrow_mut();
+        let mut totals = monthly.get(&key).unwrap_or(MonthlyRevenue {
+            month: key.month,
+            source: key.source.clone(),
+            ..MonthlyRevenue::default()
+        });
+        
+        match original {
+            Some(original) => {
+                totals.amount = totals.amount.saturating_sub(original.amount) + entry.amount;
+                if entry.amount == 0 {
+                    totals.entries = totals.entries.saturating_sub(1);
+                }
+            }
+            None => {
+                totals.amount += entry.amount;
+                totals.entries += 1;
+            }
+        }
+        monthly.insert(key, totals);
+    });
+}
+
+/// Counts `entry`, or a correction replacing `original`, in the snapshot of the month
+/// it was recorded in, and closes that month at the tranche accumulators `rates`.
+fn snapshot_revenue(timestamp: u64, rates: Vec<u128>, entry: &RevenueUpdate, original: Option<&RevenueUpdate>) {
+    update_period_snapshot(timestamp, |snapshot| {
+        if let Some(original) = original {
+            snapshot.add_revenue(original, -1);
+        }
+        snapshot.add_revenue(entry, 1);
+        snapshot.closing_rates = rates;
+    });
+}
+
+fn snapshot_payout(timestamp: u64, amount: u64, fee: u64) {
+    update_period_snapshot(timestamp, |snapshot| {
+        snapshot.paid_out += amount - fee;
+        snapshot.payout_fees += fee;
+    });
+}
+
+/// Applies `update` to the snapshot of `timestamp`'s month, opening it at the closing
+/// rates of the latest earlier month if it does not exist yet.
+fn update_period_snapshot(timestamp: u64, update: impl FnOnce(&mut PeriodSnapshot)) {
+    let month = history::month_of(timestamp);
+    PERIOD_SNAPSHOTS.with(|snapshots| {
+        let mut snapshots = snapshots.borrow_mut();
+        let mut snapshot = snapshots.get(&month).unwrap_or_else(|| {
+            let opening_rates = snapshots.range(..month).next_back()
+                .map(|(_, previous)| previous.closing_rates)
+                .unwrap_or_default();
+            PeriodSnapshot::new(month, opening_rates)
+        });
+        update(&mut snapshot);
+        snapshots.insert(month, snapshot);
+    });
+}
+
+/// Appends a balanced entry to the journal and applies it to the account balances.
+/// Traps on an unbalanced entry or a failed append, rolling back the whole call.
+fn post_journal(state: &mut VaultState, entry: JournalEntry) {
+    if !entry.is_balanced() {
+        ic_cdk::trap(format!("Unbalanced journal entry: {:?}", entry));
+    }
+    
+    JOURNAL.with(|journal| {
+        journal.borrow().append(&entry)
+            .unwrap_or_else(|e| ic_cdk::trap(format!("Failed to record journal entry: {:?}", e)))
+    });
+    
+    for line in &entry.lines {
+        *state.journal_balances.entry(line.account).or_default() += line.debit as i128 - line.credit as i128;
+    }
+}
+
+/// Changes the backers' base share. Only the DAO can do this. Revenue is run through
+/// the waterfall as deltas of the cumulative pool, so the new percentage applies to
+/// revenue reported from now on and leaves amounts already accrued untouched.
+#[update]
+fn update_revenue_share(new_percentage: u8) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if Some(caller) != state.dao_canister {
+                return Err("Only the DAO can change the revenue share".to_string());
+            }
+            
+            if state.status == VaultStatus::Refunding || state.status == VaultStatus::Matured {
+                return Err("Revenue share can no longer be changed".to_string());
+            }
+            
+            if new_percentage == 0 {
+                return Err("Revenue share must be greater than 0".to_string());
+            }
+            
+            state.waterfall.validate(state.funding_goal, new_percentage)?;
+            
+            ic_cdk::println!(
+                "Revenue share for campaign {} changed from {}% to {}%",
+                state.campaign_id, state.revenue_share_percentage, new_percentage
+            );
+            state.revenue_share_percentage = new_percentage;
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+/// Applies a DAO ruling to the revenue entry at `revenue_index`. Reversals and
+/// adjustments take the entry's backer credit back, out of the reserve first and then
+/// out of the tranche accumulators, and append a correcting entry that points at the
+/// original.
+#[update]
+fn resolve_dispute(revenue_index: u64, action: DisputeAction) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    let now = ic_cdk::api::time();
+    
+    let entry = REVENUE_HISTORY.with(|history| history.borrow().get(revenue_index))
+        .ok_or_else(|| "Revenue entry not found".to_string())?;
+    let existing = REVENUE_DISPUTES.with(|disputes| disputes.borrow().get(&revenue_index));
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if Some(caller) != state.dao_canister {
+                return Err("Only the DAO can resolve disputes".to_string());
+            }
+            
+            if existing.as_ref().is_some_and(|d| d.action != DisputeAction::Freeze) {
+                return Err("Revenue entry has already been corrected".to_string());
+            }
+            
+            let corrected_amount = match action {
+                DisputeAction::Freeze => {
+                    let frozen = freeze_backer_revenue(state, revenue_index, &entry, now)?;
+                    if frozen > 0 {
+                        post_journal(state, journal::reserve_release(String::new(), now, frozen)
+                            .reversed(format!("revenue {} frozen", revenue_index), now));
+                        update_period_snapshot(now, |snapshot| snapshot.closing_rates = tranche_rates(state));
+                    }
+                    set_dispute(revenue_index, action, None, now);
+                    return Ok(());
+                }
+                DisputeAction::Unfreeze => {
+                    if existing.is_none() {
+                        return Err("Revenue entry is not frozen".to_string());
+                    }
+                    REVENUE_DISPUTES.with(|disputes| disputes.borrow_mut().remove(&revenue_index));
+                    return Ok(());
+                }
+                DisputeAction::Reverse => 0,
+                DisputeAction::Adjust { corrected_amount } => corrected_amount,
+            };
+            
+            let reversal = reverse_backer_revenue(state, revenue_index, &entry)?;
+            state.total_revenue = state.total_revenue.saturating_sub(entry.amount) + corrected_amount;
+            
+            // Corrected amounts are in the payout token, so they are counted under it
+            if let Some(ref conversion) = entry.conversion {
+                if let Some(total) = state.revenue_by_currency.get_mut(&conversion.reported.currency) {
+                    total.reported = total.reported.saturating_sub(conversion.reported.amount);
+                    total.converted = total.converted.saturating_sub(entry.amount);
+                }
+            }
+            let conversion = state.currency_config.payout_token.clone()
+                .filter(|_| corrected_amount > 0)
+                .map(|token| Conversion {
+                    reported: RevenueAmount {
+                        amount: corrected_amount,
+                        currency: token.symbol.to_uppercase(),
+                        decimals: token.decimals,
+                    },
+                    rate: 1,
+                    rate_decimals: 0,
+                    rate_timestamp: now / 1_000_000_000,
+                });
+            if let Some(ref conversion) = conversion {
+                count_currency_revenue(state, &conversion.reported, corrected_amount);
+            }
+            
+            // The correction accrues to backers only if the original entry did, even if
+            // the vault has matured since
+            let accrual = if corrected_amount > 0 && !entry.tranche_amounts.is_empty() {
+                accrue_waterfall(state, corrected_amount)
+            } else {
+                Accrual::default()
+            };
+            let (credited, reserved) = (accrual.credited(), accrual.reserved());
+            
+            let correction = RevenueUpdate {
+                amount: corrected_amount,
+                source: entry.source.clone(),
+                timestamp: now,
+                oracle_verification: true,
+                tranche_amounts: accrual.tranche_amounts,
+                adjusts: Some(revenue_index),
+                protocol_fee: accrual.protocol_fee,
+                creator_fee: accrual.creator_fee,
+                conversion,
+                event: None,
+            };
+            let correction_index = record_revenue(&correction);
+            snapshot_revenue(now, tranche_rates(state), &correction, Some(&entry));
+            set_dispute(revenue_index, action, Some(correction_index), now);
+            
+            let original = journal::revenue_accrual(
+                String::new(),
+                now,
+                entry.amount,
+                reversal.from_payable,
+                reversal.from_reserve,
+                entry.protocol_fee,
+            );
+            post_journal(state, original.reversed(format!("revenue {} reversed", revenue_index), now));
+            post_journal(state, journal::revenue_accrual(
+                format!("revenue {}", correction_index),
+                now,
+                corrected_amount,
+                credited,
+                reserved,
+                accrual.protocol_fee,
+            ));
+            hold_reserve(state, correction_index, accrual.held, now);
+            
+            ic_cdk::println!(
+                "Revenue entry {} corrected from {} to {} (entry {})",
+                revenue_index, entry.amount, corrected_amount, correction_index
+            );
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    // Freezes hold an entry's reserve, unfreezes and corrections change what is due
+    schedule_reserve_release();
+    Ok(())
+}
+
+fn set_dispute(revenue_index: u64, action: DisputeAction, correction_index: Option<u64>, now: u64) {
+    REVENUE_DISPUTES.with(|disputes| {
+        disputes.borrow_mut().insert(revenue_index, RevenueDispute {
+            revenue_index,
+            action,
+            correction_index,
+            updated_at: now,
+        });
+    });
+}
+
+fn entry_disputed(revenue_index: u64) -> bool {
+    REVENUE_DISPUTES.with(|disputes| disputes.borrow().contains_key(&revenue_index))
+}
+
+/// Holds `held` of entry `revenue_index` in the reserve until its holdback period ends.
+fn hold_reserve(state: &VaultState, revenue_index: u64, held: Vec<u64>, now: u64) {
+    if held.iter().all(|amount| *amount == 0) {
+        return;
+    }
+    RESERVE_HOLDS.with(|holds| {
+        holds.borrow_mut().insert(revenue_index, ReserveHold {
+            revenue_index,
+            held,
+            release_at: now.saturating_add(state.reserve_config.holdback_period),
+        });
+    });
+}
+
+/// Releases to backers up to `limit` holds whose holdback period has passed without a
+/// dispute on their entry. Returns how many were released.
+fn release_due_reserve(limit: usize) -> usize {
+    let now = ic_cdk::api::time();
+    let due: Vec<ReserveHold> = RESERVE_HOLDS.with(|holds| {
+        holds.borrow().iter()
+            .map(|(_, hold)| hold)
+            .filter(|hold| hold.is_due(now) && !entry_disputed(hold.revenue_index))
+            .take(limit)
+            .collect()
+    });
+    if due.is_empty() {
+        return 0;
+    }
+    
+    VAULT_STATE.with(|state_ref| {
+        if let Some(ref mut state) = *state_ref.borrow_mut() {
+            for hold in &due {
+                for (tranche, &amount) in state.tranches.iter_mut().zip(&hold.held) {
+                    credit_tranche(tranche, amount);
+                }
+                let released = hold.total();
+                state.total_distributable += released;
+                state.reserve_totals.held = state.reserve_totals.held.saturating_sub(released);
+                state.reserve_totals.released += released;
+                post_journal(state, journal::reserve_release(
+                    format!("revenue {} reserve", hold.revenue_index),
+                    now,
+                    released,
+                ));
+                RESERVE_HOLDS.with(|holds| holds.borrow_mut().remove(&hold.revenue_index));
+            }
+            update_period_snapshot(now, |snapshot| snapshot.closing_rates = tranche_rates(state));
+        }
+    });
+    due.len()
+}
+
+/// Moves a funded vault to `Matured` once its term has elapsed or its revenue cap
+/// has been paid out. Returns true if this call made the transition.
+fn check_maturity(state: &mut VaultState, now: u64) -> bool {
+    if state.status != VaultStatus::Funded {
+        return false;
+    }
+    
+    let expired = match (state.term.duration, state.term_start) {
+        (Some(duration), Some(start)) => now >= start.saturating_add(duration),
+        _ => false,
+    };
+    let capped = term_revenue_cap(state).is_some_and(|cap| backer_revenue(state) >= cap);
+    
+    if expired || capped {
+        state.status = VaultStatus::Matured;
+        state.matured_at = Some(now);
+        ic_cdk::println!("Campaign {} matured", state.campaign_id);
+    }
+    
+    expired || capped
+}
+
+fn term_revenue_cap(state: &VaultState) -> Option<u64> {
+    state.term.revenue_cap_bps.map(|cap_bps| {
+        (state.current_funding as u128 * cap_bps as u128 / shares::BPS_SCALE as u128).min(u64::MAX as u128) as u64
+    })
+}
+
+/// Matures the campaign if its term has ended and tells the factory and NFT registry.
+/// The vault also does this on its own when the term runs out, and retries the
+/// notification from the outbox until both accept it; this call just sends it now.
+#[update]
+async fn mature_campaign() -> Result<(), String> {
+    mature().await
+}
+
+async fn mature() -> Result<(), String> {
+    let now = ic_cdk::api::time();
+    
+    let notified = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            check_maturity(state, now);
+            if state.status != VaultStatus::Matured {
+                return Err("Revenue-share term has not ended".to_string());
+            }
+            Ok(state.maturity_notified)
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    if notified {
+        return Ok(());
+    }
+    notify_maturity().await
+}
+
+/// Sends the maturity notice through the outbox, reusing a queued one so it keeps a
+/// single key. If it fails it stays queued and is retried with backoff.
+async fn notify_maturity() -> Result<(), String> {
+    let key = format!("maturity:{}", get_campaign_id());
+    let queued = pending_outbox_item(&key);
+    let id = queued.unwrap_or_else(|| enqueue(Some(key), Effect::NotifyMaturity));
+    
+    process_outbox_item(id).await
+}
+
+async fn call_notify_maturity() -> Result<(), Failure> {
+    let (campaign_id, factory, nft_registry) = VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref()
+            .map(|s| (s.campaign_id, s.factory_canister, s.nft_registry_canister))
+            .ok_or_else(|| Failure::Rejected("Vault not initialized".to_string()))
+    })?;
+    
+    let result: Result<Result<(), String>, _> = call(
+        factory,
+        "complete_campaign",
+        (campaign_id,),
+    ).await;
+    
+    match result {
+        Ok(Ok(())) => {},
+        Ok(Err(e)) => return Err(Failure::Rejected(e)),
+        Err(e) => return Err(Failure::Uncertain(format!("Failed to call campaign factory: {:?}", e))),
+    }
+    
+    // Both calls are idempotent, so a retry after a registry failure may repeat the first
+    if let Some(nft_registry) = nft_registry {
+        let result: Result<Result<u64, String>, _> = call(
+            nft_registry,
+            "mark_vault_matured",
+            (),
+        ).await;
+        
+        match result {
+            Ok(Ok(_)) => {},
+            Ok(Err(e)) => return Err(Failure::Rejected(e)),
+            Err(e) => return Err(Failure::Uncertain(format!("Failed to call NFT registry: {:?}", e))),
+        }
+    }
+    
+    Ok(())
+}
+
+/// Arms a one-shot timer for the end of a funded vault's term, or straight away for a
+/// matured vault whose notice has not gone out yet, replacing any earlier timer.
+fn schedule_maturity() {
+    let at = VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().and_then(|s| match s.status {
+            VaultStatus::Funded => s.term.duration.zip(s.term_start).map(|(duration, start)| start.saturating_add(duration)),
+            VaultStatus::Matured if !s.maturity_notified => Some(0),
+            _ => None,
+        })
+    });
+    
+    MATURITY_TIMER.with(|timer| {
+        let mut timer = timer.borrow_mut();
+        if let Some(timer_id) = timer.take() {
+            ic_cdk_timers::clear_timer(timer_id);
+        }
+        
+        if let Some(at) = at {
+            let delay = Duration::from_nanos(at.saturating_sub(ic_cdk::api::time()));
+            *timer = Some(ic_cdk_timers::set_timer(delay, || {
+                MATURITY_TIMER.with(|timer| *timer.borrow_mut() = None);
+                ic_cdk::futures::spawn(async {
+                    if let Err(e) = mature().await {
+                        ic_cdk::println!("Failed to mature campaign: {}", e);
+                    }
+                });
+            }));
+        }
+    });
+}
+
+fn revenue_reporter(state: &VaultState, caller: Principal) -> Option<RevenueReporter> {
+    if Some(caller) == state.oracle_canister {
+        Some(RevenueReporter::Oracle)
+    } else if Some(caller) == state.dao_canister {
+        Some(RevenueReporter::Dao)
+    } else if caller == state.creator {
+        Some(RevenueReporter::Creator)
+    } else {
+        None
+    }
+}
+
+/// Runs the backers' share of `revenue` through the waterfall and credits each
+/// tranche's per-unit accumulator. Revenue only accrues once the campaign is funded,
+/// and investing stops at that point, so every lot's units are fixed for the whole
+/// accrual period. Protocol and creator revenue fees come off the pool first.
+fn accrue_backer_revenue(state: &mut VaultState, revenue: u64) -> Accrual {
+    if state.status != VaultStatus::Funded {
+        return Accrual::default();
+    }
+    accrue_waterfall(state, revenue)
+}
+
+/// Credits the backers' share of `revenue` whatever the vault's status, for
+/// corrections to entries that accrued while it was funded.
+fn accrue_waterfall(state: &mut VaultState, revenue: u64) -> Accrual {
+    let gross_pool = backer_pool_delta(state, revenue);
+    let protocol_fee = shares::apply_bps(gross_pool, state.protocol_fees.revenue_fee_bps);
+    let creator_fee = shares::apply_bps(gross_pool, state.creator_fee_bps);
+    let pool = gross_pool - protocol_fee - creator_fee;
+    let (amounts, _) = state.waterfall.distribute(&state.tranches, pool);
+    let (credited, held) = state.reserve_config.split(&amounts);
+    
+    for ((tranche, &amount), &credit) in state.tranches.iter_mut().zip(&amounts).zip(&credited) {
+        credit_tranche(tranche, credit);
+        tranche.received += amount;
+    }
+    
+    state.waterfall_revenue += revenue;
+    state.total_distributable += credited.iter().sum::<u64>();
+    state.reserve_totals.held += held.iter().sum::<u64>();
+    state.fee_totals.revenue += protocol_fee;
+    state.fee_totals.creator += creator_fee;
+    
+    Accrual {
+        tranche_amounts: amounts,
+        held,
+        protocol_fee,
+        creator_fee,
+    }
+}
+
+fn credit_tranche(tranche: &mut TrancheState, amount: u64) {
+    let (revenue_per_unit, carry) = shares::accrue(
+        tranche.revenue_per_unit,
+        tranche.revenue_per_unit_carry,
+        amount,
+        tranche.raised,
+    );
+    tranche.revenue_per_unit = revenue_per_unit;
+    tranche.revenue_per_unit_carry = carry;
+}
+
+fn debit_tranche(tranche: &mut TrancheState, amount: u64) {
+    let (revenue_per_unit, carry) = shares::reverse(
+        tranche.revenue_per_unit,
+        tranche.revenue_per_unit_carry,
+        amount,
+        tranche.raised,
+    );
+    tranche.revenue_per_unit = revenue_per_unit;
+    tranche.revenue_per_unit_carry = carry;
+}
+
+/// What one revenue update credited: per-tranche amounts, the part of them held in
+/// the reserve, and the fees taken first.
+#[derive(Default)]
+struct Accrual {
+    tranche_amounts: Vec<u64>,
+    held: Vec<u64>,
+    protocol_fee: u64,
+    creator_fee: u64,
+}
+
+impl Accrual {
+    /// Credited to backers straight away
+    fn credited(&self) -> u64 {
+        self.tranche_amounts.iter().sum::<u64>() - self.reserved()
+    }
+    
+    fn reserved(&self) -> u64 {
+        self.held.iter().sum()
+    }
+}
+
+/// Undoes what `entry` credited to the tranches: its own held amount is cancelled, what
+/// it already released is covered from other entries' holds, and only the rest comes
+/// out of the accumulators. Backers who already claimed more than they are now owed
+/// simply have nothing claimable until new revenue covers it. Rejected, with nothing
+/// changed, if the rest is more than the backers have been credited.
+fn reverse_backer_revenue(state: &mut VaultState, revenue_index: u64, entry: &RevenueUpdate) -> Result<Reversal, String> {
+    let mut reversal = Reversal::default();
+    if entry.tranche_amounts.is_empty() {
+        return Ok(reversal);
+    }
+    
+    // Work out the draws on copies first so a rejected reversal leaves the holds alone
+    let mut needed = entry.tranche_amounts.clone();
+    let mut own = RESERVE_HOLDS.with(|holds| holds.borrow().get(&revenue_index));
+    let cancelled = own.as_mut().map_or(0, |hold| hold.draw(&mut needed));
+    
+    let mut drawn = Vec::new();
+    RESERVE_HOLDS.with(|holds| {
+        let others = holds.borrow().iter()
+            .map(|(_, hold)| hold)
+            .filter(|hold| hold.revenue_index != revenue_index && !entry_disputed(hold.revenue_index))
+            .collect::<Vec<_>>();
+        for mut hold in others {
+            if needed.iter().all(|amount| *amount == 0) {
+                break;
+            }
+            let used = hold.draw(&mut needed);
+            if used > 0 {
+                drawn.push((hold, used));
+            }
+        }
+    });
+    
+    reversal.from_payable = needed.iter().sum();
+    state.total_distributable = state.total_distributable.checked_sub(reversal.from_payable)
+        .ok_or_else(|| format!(
+            "Cannot take back {} of backer revenue; only {} has been credited",
+            reversal.from_payable, state.total_distributable
+        ))?;
+    
+    RESERVE_HOLDS.with(|holds| {
+        let mut holds = holds.borrow_mut();
+        if own.is_some() {
+            holds.remove(&revenue_index);
+        }
+        for (hold, _) in &drawn {
+            if hold.total() == 0 {
+                holds.remove(&hold.revenue_index);
+            } else {
+                holds.insert(hold.revenue_index, hold.clone());
+            }
+        }
+    });
+    let used: u64 = drawn.iter().map(|(_, used)| used).sum();
+    state.reserve_totals.cancelled += cancelled;
+    state.reserve_totals.used += used;
+    reversal.from_reserve = cancelled + used;
+    state.reserve_totals.held = state.reserve_totals.held.saturating_sub(reversal.from_reserve);
+    
+    for ((tranche, &amount), &uncovered) in state.tranches.iter_mut().zip(&entry.tranche_amounts).zip(&needed) {
+        debit_tranche(tranche, uncovered);
+        tranche.received = tranche.received.saturating_sub(amount);
+    }
+    
+    state.waterfall_revenue = state.waterfall_revenue.saturating_sub(entry.amount);
+    state.fee_totals.revenue = state.fee_totals.revenue.saturating_sub(entry.protocol_fee);
+    state.fee_totals.creator = state.fee_totals.creator.saturating_sub(entry.creator_fee);
+    Ok(reversal)
+}
+
+/// Moves what `entry` credited to the tranches and is not already held into its
+/// reserve hold, so only this entry stops paying out while it is disputed. The hold is
+/// released once the entry is unfrozen and cancelled if it is reversed. Returns the
+/// amount moved.
+fn freeze_backer_revenue(state: &mut VaultState, revenue_index: u64, entry: &RevenueUpdate, now: u64) -> Result<u64, String> {
+    if entry.tranche_amounts.is_empty() {
+        return Ok(0);
+    }
+    
+    let mut hold = RESERVE_HOLDS.with(|holds| holds.borrow().get(&revenue_index))
+        .unwrap_or(ReserveHold { revenue_index, held: Vec::new(), release_at: now });
+    let moved = hold.hold_all(&entry.tranche_amounts);
+    let frozen: u64 = moved.iter().sum();
+    state.total_distributable = state.total_distributable.checked_sub(frozen)
+        .ok_or_else(|| format!("Cannot freeze {} of backer revenue; only {} is distributable", frozen, state.total_distributable))?;
+    
+    for (tranche, &amount) in state.tranches.iter_mut().zip(&moved) {
+        debit_tranche(tranche, amount);
+    }
+    state.reserve_totals.held += frozen;
+    RESERVE_HOLDS.with(|holds| holds.borrow_mut().insert(revenue_index, hold));
+    Ok(frozen)
+}
+
+/// Where a reversed entry's backer credit was taken back from.
+#[derive(Default)]
+struct Reversal {
+    from_reserve: u64,
+    from_payable: u64,
+}
+
+/// Backer revenue credited or held in the reserve, which together count towards the
+/// term's revenue cap.
+fn backer_revenue(state: &VaultState) -> u64 {
+    state.total_distributable + state.reserve_totals.held
+}
+
+/// Backers' pool for `revenue` reported on top of what has already been through the
+/// waterfall, never more than `revenue` itself and limited to whatever is left under
+/// the term's revenue cap.
+fn backer_pool_delta(state: &VaultState, revenue: u64) -> u64 {
+    let before = state.waterfall_revenue;
+    let after = before.saturating_add(revenue);
+    let pool = state.waterfall.backer_pool(state.revenue_share_percentage, after)
+        .saturating_sub(state.waterfall.backer_pool(state.revenue_share_percentage, before))
+        .min(revenue);
+    
+    match term_revenue_cap(state) {
+        Some(cap) => pool.min(cap.saturating_sub(backer_revenue(state))),
+        None => pool,
+    }
+}
+
+fn claimable_amount(info: &BackerInfo, tranche_rates: &[u128]) -> u64 {
+    info.lots.iter().map(|lot| lot_claimable(lot, tranche_rates)).sum()
+}
+
+fn lot_claimable(lot: &InvestmentLot, tranche_rates: &[u128]) -> u64 {
+    let rate = tranche_rates.get(lot.tranche as usize).copied().unwrap_or(0);
+    shares::accrued(lot.amount, rate).saturating_sub(lot.claimed)
+}
+
+/// Marks everything claimable on one lot as claimed and returns the amount.
+/// Reserved before a payout is sent and released with `release_lot_claims` if it fails.
+fn reserve_lot_claim(backer: &Principal, lot_index: u32, tranche_rates: &[u128]) -> u64 {
+    update_backer(backer, |info| {
+        let claimable = match info.lots.get_mut(lot_index as usize) {
+            Some(lot) => {
+                let claimable = lot_claimable(lot, tranche_rates);
+                lot.claimed += claimable;
+                claimable
+            }
+            None => 0,
+        };
+        info.total_claimed += claimable;
+        claimable
+    })
+    .unwrap_or(0)
+}
+
+fn release_lot_claims(reserved: &[(Principal, u32, u64)]) {
+    for (backer, lot_index, amount) in reserved {
+        update_backer(backer, |info| {
+            if let Some(lot) = info.lots.get_mut(*lot_index as usize) {
+                lot.claimed -= amount;
+            }
+            info.total_claimed -= amount;
+        });
+    }
+}
+
+/// Pays the caller everything accrued to the positions they hold: their own lots that
+/// have no NFT yet, plus every position NFT of this vault they currently own. Returns
+/// the amount paid after the protocol payout fee.
+#[update]
+async fn claim_payout() -> Result<u64, OperationError> {
+    let caller = ic_cdk::api::msg_caller();
+    check_not_paused(Operation::Distribution)?;
+    let _guard = Guard::acquire(Lock::Principal(caller))?;
+    
+    let mut lots: Vec<(Principal, u32)> = get_backer(&caller)
+        .map(|info| {
+            info.lots.iter().enumerate()
+                .filter(|(_, lot)| lot.nft_token_id.is_none())
+                .map(|(lot_index, _)| (caller, lot_index as u32))
+                .collect()
+        })
+        .unwrap_or_default();
+    
+    if let Some(nft_registry) = get_nft_registry_canister() {
+        let token_ids = tokens_of(nft_registry, caller).await?;
+        let now = ic_cdk::api::time();
+        for token_id in &token_ids {
+            record_owner(*token_id, caller, now);
+        }
+        lots.extend(POSITIONS.with(|positions| {
+            let positions = positions.borrow();
+            token_ids.iter().filter_map(|token_id| positions.get(token_id)).collect::<Vec<_>>()
+        }));
+    }
+    
+    // Reserve the claim before the payout call; the outbox releases it if the stream is rejected
+    check_not_paused(Operation::Distribution)?;
+    let tranche_rates = get_tranche_rates();
+    let reserved: Vec<(Principal, u32, u64)> = lots.into_iter()
+        .map(|(backer, lot_index)| (backer, lot_index, reserve_lot_claim(&backer, lot_index, &tranche_rates)))
+        .filter(|(_, _, amount)| *amount > 0)
+        .collect();
+    
+    let amount: u64 = reserved.iter().map(|(_, _, amount)| amount).sum();
+    if amount == 0 {
+        return Err("Nothing to claim".to_string().into());
+    }
+    
+    let fee = payout_fee(amount);
+    let id = enqueue(None, Effect::Payout {
+        payouts: vec![(caller, amount - fee)],
+        claims: reserved,
+        gross: amount,
+        fee,
+        memo: format!("claim by {}", caller.to_text()),
+    });
+    
+    process_outbox_item(id).await.map(|()| amount - fee).map_err(OperationError::from)
+}
+
+/// Keeper job: pays accrued revenue on the lots of up to `batch_size` backers, resuming
+/// after the backer where the previous batch stopped. Call repeatedly until `complete`.
+/// Lots with a position NFT are paid to the token's current owner; if the registry
+/// does not return one, the lot is skipped and stays claimable.
+#[update]
+async fn distribute_payouts(batch_size: u32) -> Result<PayoutBatch, OperationError> {
+    distribute_batch(batch_size).await
+}
+
+async fn distribute_batch(batch_size: u32) -> Result<PayoutBatch, OperationError> {
+    let batch_size = batch_size.clamp(1, MAX_PAYOUT_BATCH) as usize;
+    check_not_paused(Operation::Distribution)?;
+    
+    // Batches run one at a time so two cannot start from the same cursor
+    let _guard = Guard::acquire(Lock::Distribution)?;
+    
+    let cursor = VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().and_then(|s| s.payout_cursor)
+    });
+    
+    let batch: Vec<(Principal, BackerInfo)> = BACKERS.with(|backers| {
+        let backers = backers.borrow();
+        match cursor {
+            Some(cursor) => backers.range((Bound::Excluded(cursor), Bound::Unbounded)).take(batch_size).collect(),
+            None => backers.iter().take(batch_size).collect(),
+        }
+    });
+    
+    let next_cursor = if batch.len() == batch_size {
+        batch.last().map(|(backer, _)| *backer)
+    } else {
+        None
+    };
+    
+    let token_ids: Vec<u64> = batch.iter()
+        .flat_map(|(_, info)| info.lots.iter().filter_map(|lot| lot.nft_token_id))
+        .collect();
+    let owners: BTreeMap<u64, Principal> = if token_ids.is_empty() {
+        BTreeMap::new()
+    } else {
+        let nft_registry = get_nft_registry_canister()
+            .ok_or_else(|| "NFT registry not configured".to_string())?;
+        let owners = owners_of(nft_registry, token_ids.clone()).await?;
+        token_ids.into_iter()
+            .zip(owners)
+            .filter_map(|(token_id, owner)| owner.map(|owner| (token_id, owner)))
+            .collect()
+    };
+    let now = ic_cdk::api::time();
+    for (token_id, owner) in &owners {
+        record_owner(*token_id, *owner, now);
+    }
+    
+    // Reserve every payout in the batch before the await, unless paused in the meantime
+    check_not_paused(Operation::Distribution)?;
+    let tranche_rates = get_tranche_rates();
+    let mut reserved = Vec::new();
+    let mut unresolved_positions = Vec::new();
+    let mut payees: BTreeMap<Principal, u64> = BTreeMap::new();
+    for (backer, info) in &batch {
+        for (lot_index, lot) in info.lots.iter().enumerate() {
+            let Some(payee) = lot_payee(*backer, lot, &owners) else {
+                unresolved_positions.extend(lot.nft_token_id);
+                continue;
+            };
+            
+            let amount = reserve_lot_claim(backer, lot_index as u32, &tranche_rates);
+            if amount == 0 {
+                continue;
+            }
+            *payees.entry(payee).or_default() += amount;
+            reserved.push((*backer, lot_index as u32, amount));
+        }
+    }
+    
+    let gross: u64 = payees.values().sum();
+    let mut fees = 0;
+    let payouts: Vec<(Principal, u64)> = payees.into_iter()
+        .map(|(payee, amount)| {
+            let fee = payout_fee(amount);
+            fees += fee;
+            (payee, amount - fee)
+        })
+        .collect();
+    
+    // A payout that may have gone through stays reserved and queued; the batch moves on
+    let mut outbox_item = None;
+    let mut applied = true;
+    if !payouts.is_empty() {
+        let id = enqueue(None, Effect::Payout {
+            payouts: payouts.clone(),
+            claims: reserved,
+            gross,
+            fee: fees,
+            memo: format!("distribution to {} payees", payouts.len()),
+        });
+        if let Err(e) = process_outbox_item(id).await {
+            if outbox_status(id) != Some(OutboxStatus::Pending) {
+                return Err(e.into());
+            }
+            applied = false;
+        }
+        outbox_item = Some(id);
+    }
+    
+    VAULT_STATE.with(|state_ref| {
+        if let Some(ref mut state) = *state_ref.borrow_mut() {
+            state.payout_cursor = next_cursor;
+        }
+    });
+    
+    Ok(PayoutBatch {
+        payouts,
+        next_cursor,
+        complete: next_cursor.is_none(),
+        outbox_item,
+        applied,
+        unresolved_positions,
+    })
+}
+
+/// Who a lot's revenue is paid to: the investor until it is minted, then the NFT's
+/// owner. `None` when the registry did not return an owner, which may be an error or
+/// a transfer in flight; the lot stays claimable and is paid on a later pass.
+fn lot_payee(investor: Principal, lot: &InvestmentLot, owners: &BTreeMap<u64, Principal>) -> Option<Principal> {
+    match lot.nft_token_id {
+        Some(token_id) => owners.get(&token_id).copied(),
+        None => Some(investor),
+    }
+}
+
+/// Records `owner` as holding position `token_id` from `now` on, unless they already
+/// are its owner of record. Transfers reported by the registry land here, as do the
+/// owners a payout finds, in case a report was lost.
+fn record_owner(token_id: u64, owner: Principal, now: u64) {
+    let Some((investor, lot_index)) = POSITIONS.with(|positions| positions.borrow().get(&token_id)) else {
+        return;
+    };
+    let current = position_owners(token_id).last().map_or(investor, |record| record.owner);
+    if current == owner {
+        return;
+    }
+    
+    let tranche = get_backer(&investor)
+        .and_then(|info| info.lots.get(lot_index as usize).map(|lot| lot.tranche))
+        .unwrap_or(0);
+    let rate = get_tranche_rates().get(tranche as usize).copied().unwrap_or(0);
+    POSITION_OWNERS.with(|owners| {
+        owners.borrow_mut().insert((token_id, now), OwnerRecord { owner, since: now, rate });
+    });
+}
+
+fn position_owners(token_id: u64) -> Vec<OwnerRecord> {
+    POSITION_OWNERS.with(|owners| {
+        owners.borrow().range((token_id, 0)..=(token_id, u64::MAX))
+            .map(|(_, record)| record)
+            .collect()
+    })
+}
+
+/// Called by the NFT registry when a position NFT changes hands, so that revenue from
+/// then on is attributed to the new owner.
+#[update]
+fn notify_position_transfer(token_id: u64, to: Principal) -> Result<(), String> {
+    if Some(ic_cdk::api::msg_caller()) != get_nft_registry_canister() {
+        return Err("Only the NFT registry can report transfers".to_string());
+    }
+    record_owner(token_id, to, ic_cdk::api::time());
+    Ok(())
+}
+
+async fn tokens_of(nft_registry: Principal, owner: Principal) -> Result<Vec<u64>, String> {
+    let result: Result<Vec<u64>, _> = call(nft_registry, "icrc7_tokens_of", (owner,)).await;
+    
+    match result {
+        Ok(token_ids) => Ok(token_ids),
+        Err(e) => Err(format!("Failed to call NFT registry: {:?}", e)),
+    }
+}
+
+async fn owners_of(nft_registry: Principal, token_ids: Vec<u64>) -> Result<Vec<Option<Principal>>, String> {
+    let result: Result<Vec<Option<Principal>>, _> = call(nft_registry, "owners_of", (token_ids,)).await;
+    
+    match result {
+        Ok(owners) => Ok(owners),
+        Err(e) => Err(format!("Failed to call NFT registry: {:?}", e)),
+    }
+}
+
+async fn create_streams(payouts: Vec<(Principal, u64)>, key: String) -> Result<(), Failure> {
+    let stream_canister = get_stream_canister()
+        .ok_or_else(|| Failure::Rejected("Stream canister not configured".to_string()))?;
+    
+    let result: Result<Result<Vec<u64>, String>, _> = call(
+        stream_canister,
+        "create_streams",
+        (payouts, Some(key)),
+    ).await;
+    
+    match result {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(Failure::Rejected(e)),
+        Err(e) => Err(Failure::Uncertain(format!("Failed to create streams: {:?}", e))),
+    }
+}
+
+/// Records an effect in the outbox and returns its id. Without an explicit key the
+/// item is keyed by its id.
+fn enqueue(key: Option<String>, effect: Effect) -> u64 {
+    let now = ic_cdk::api::time();
+    let after_last = OUTBOX.with(|outbox| outbox.borrow().last_key_value().map(|(id, _)| id + 1).unwrap_or(0));
+    let id = VAULT_STATE.with(|state_ref| match *state_ref.borrow_mut() {
+        Some(ref mut state) => {
+            let id = state.next_outbox_id.max(after_last);
+            state.next_outbox_id = id + 1;
+            id
+        }
+        None => after_last,
+    });
+    let key = key.unwrap_or_else(|| format!("outbox:{}", id));
+    store_outbox_item(OutboxItem::new(id, key, effect, now));
+    id
+}
+
+/// Writes an outbox item and keeps the key index and the pending and completed sets
+/// in step with it.
+fn store_outbox_item(item: OutboxItem) {
+    let previous = OUTBOX.with(|outbox| outbox.borrow_mut().insert(item.id, item.clone()));
+    if let Some(previous) = previous {
+        OUTBOX_PENDING.with(|pending| pending.borrow_mut().remove(&(previous.next_attempt_at, previous.id)));
+    }
+    
+    OUTBOX_KEYS.with(|keys| keys.borrow_mut().insert(item.idempotency_key.clone(), item.id));
+    if item.status == OutboxStatus::Pending {
+        OUTBOX_PENDING.with(|pending| pending.borrow_mut().insert((item.next_attempt_at, item.id), ()));
+    } else {
+        let completed_at = item.completed_at.unwrap_or(item.created_at);
+        OUTBOX_COMPLETED.with(|completed| completed.borrow_mut().insert((completed_at, item.id), ()));
+    }
+}
+
+/// The pending item carrying `key`, if there is one.
+fn pending_outbox_item(key: &str) -> Option<u64> {
+    let id = OUTBOX_KEYS.with(|keys| keys.borrow().get(&key.to_string()))?;
+    (outbox_status(id) == Some(OutboxStatus::Pending)).then_some(id)
+}
+
+/// Drops up to `MAX_OUTBOX_BATCH` applied and cancelled items that completed more
+/// than `outbox::RETENTION` ago.
+fn prune_outbox(now: u64) {
+    let expired: Vec<(u64, u64)> = OUTBOX_COMPLETED.with(|completed| {
+        completed.borrow().iter()
+            .map(|(key, ())| key)
+            .take_while(|(completed_at, _)| completed_at.saturating_add(outbox::RETENTION) <= now)
+            .take(MAX_OUTBOX_BATCH as usize)
+            .collect()
+    });
+    
+    for (completed_at, id) in expired {
+        OUTBOX_COMPLETED.with(|completed| completed.borrow_mut().remove(&(completed_at, id)));
+        if let Some(item) = OUTBOX.with(|outbox| outbox.borrow_mut().remove(&id)) {
+            OUTBOX_KEYS.with(|keys| {
+                let mut keys = keys.borrow_mut();
+                if keys.get(&item.idempotency_key) == Some(id) {
+                    keys.remove(&item.idempotency_key);
+                }
+            });
+        }
+    }
+}
+
+fn outbox_status(id: u64) -> Option<OutboxStatus> {
+    OUTBOX.with(|outbox| outbox.borrow().get(&id).map(|item| item.status))
+}
+
+/// Dispatches one pending outbox item. `Ok` once the effect is confirmed and applied;
+/// otherwise the item was cancelled, or stays pending and is retried with backoff.
+async fn process_outbox_item(id: u64) -> Result<(), String> {
+    let item = OUTBOX.with(|outbox| outbox.borrow().get(&id))
+        .ok_or_else(|| format!("Outbox item {} not found", id))?;
+    
+    if item.status != OutboxStatus::Pending {
+        return Err(format!("Outbox item {} is no longer pending", id));
+    }
+    
+    if outbox_item_paused(&item) {
+        return Err(format!("Outbox item {} is held while distribution is paused", id));
+    }
+    
+    let guard = Guard::acquire(Lock::OutboxItem(id))?;
+    let result = dispatch(&item).await;
+    drop(guard);
+    
+    let now = ic_cdk::api::time();
+    let mut item = item;
+    let outcome = match result {
+        Ok(token_id) => {
+            apply_effect(&item.effect, token_id);
+            item.complete(OutboxStatus::Applied, None, now);
+            Ok(())
+        }
+        // A refused mint or maturity notice can still succeed once the other canister is fixed
+        Err(Failure::Rejected(e)) if !matches!(item.effect, Effect::MintPosition { .. } | Effect::NotifyMaturity) => {
+            cancel_effect(&item.effect);
+            item.complete(OutboxStatus::Cancelled, Some(e.clone()), now);
+            Err(e)
+        }
+        Err(Failure::Rejected(e)) | Err(Failure::Uncertain(e)) => {
+            ic_cdk::println!("Outbox item {} failed, will retry: {}", id, e);
+            item.retry_later(e.clone(), now);
+            Err(format!("{} (queued for retry as outbox item {})", e, id))
+        }
+    };
+    
+    store_outbox_item(item);
+    prune_outbox(now);
+    schedule_outbox_retry();
+    outcome
+}
+
+/// Makes the call behind an effect. Mints return the new token id.
+async fn dispatch(item: &OutboxItem) -> Result<Option<u64>, Failure> {
+    let key = item.idempotency_key.clone();
+    match &item.effect {
+        Effect::Payout { payouts, .. } => create_streams(payouts.clone(), key).await.map(|()| None),
+        Effect::FeeStream { treasury, amount } => create_streams(vec![(*treasury, *amount)], key).await.map(|()| None),
+        Effect::MintPosition { backer, lot_index } => {
+            let lot = get_backer(backer)
+                .and_then(|info| info.lots.get(*lot_index as usize).cloned())
+                .ok_or_else(|| Failure::Rejected("Investment lot not found".to_string()))?;
+            let nft_registry = get_nft_registry_canister()
+                .ok_or_else(|| Failure::Rejected("NFT registry not configured".to_string()))?;
+            call_mint(nft_registry, *backer, &lot, key).await.map(Some)
+        }
+        Effect::NotifyMaturity => call_notify_maturity().await.map(|()| None),
+    }
+}
+
+/// Applies a confirmed effect to the vault's books.
+fn apply_effect(effect: &Effect, token_id: Option<u64>) {
+    match effect {
+        Effect::Payout { gross, fee, memo, .. } => record_claimed(*gross, *fee, memo.clone()),
+        Effect::FeeStream { amount, .. } => {
+            post_fee_collection(JournalAccount::StreamedPayouts, *amount, "revenue fees".to_string());
+        }
+        Effect::MintPosition { backer, lot_index } => {
+            if let Some(token_id) = token_id {
+                update_backer(backer, |backer_info| {
+                    if let Some(lot) = backer_info.lots.get_mut(*lot_index as usize) {
+                        lot.nft_token_id = Some(token_id);
+                    }
+                });
+                POSITIONS.with(|positions| positions.borrow_mut().insert(token_id, (*backer, *lot_index)));
+            }
+        }
+        Effect::NotifyMaturity => VAULT_STATE.with(|state_ref| {
+            if let Some(ref mut state) = *state_ref.borrow_mut() {
+                state.maturity_notified = true;
+            }
+        }),
+    }
+}
+
+/// Releases what a rejected effect had reserved.
+fn cancel_effect(effect: &Effect) {
+    match effect {
+        Effect::Payout { claims, .. } => release_lot_claims(claims),
+        Effect::FeeStream { amount, .. } => release_fee_collection(0, *amount),
+        Effect::MintPosition { .. } | Effect::NotifyMaturity => {}
+    }
+}
+
+/// Keeper job: retries up to `limit` outbox items that are due. Returns how many were applied.
+#[update]
+async fn process_outbox(limit: u32) -> u32 {
+    process_due_outbox(limit).await
+}
+
+async fn process_due_outbox(limit: u32) -> u32 {
+    let limit = limit.clamp(1, MAX_OUTBOX_BATCH) as usize;
+    let now = ic_cdk::api::time();
+    let due: Vec<u64> = OUTBOX_PENDING.with(|pending| {
+        pending.borrow().iter()
+            .map(|(key, ())| key)
+            .take_while(|(next_attempt_at, _)| *next_attempt_at <= now)
+            .filter_map(|(_, id)| OUTBOX.with(|outbox| outbox.borrow().get(&id)))
+            .filter(|item| !outbox_item_paused(item))
+            .map(|item| item.id)
+            .take(limit)
+            .collect()
+    });
+    
+    let mut applied = 0;
+    for id in due {
+        if process_outbox_item(id).await.is_ok() {
+            applied += 1;
+        }
+    }
+    applied
+}
+
+/// Payouts and fee streams wait in the outbox while distribution is paused.
+fn outbox_item_paused(item: &OutboxItem) -> bool {
+    !matches!(item.effect, Effect::MintPosition { .. } | Effect::NotifyMaturity) && check_not_paused(Operation::Distribution).is_err()
+}
+
+/// Arms a one-shot timer for the earliest pending retry, replacing any later one.
+fn schedule_outbox_retry() {
+    let next = OUTBOX_PENDING.with(|pending| {
+        pending.borrow().iter()
+            .map(|(key, ())| key)
+            .filter(|(_, id)| !guard::is_held(&Lock::OutboxItem(*id)))
+            .find(|(_, id)| {
+                OUTBOX.with(|outbox| outbox.borrow().get(id)).is_some_and(|item| !outbox_item_paused(&item))
+            })
+            .map(|(next_attempt_at, _)| next_attempt_at)
+    });
+    
+    OUTBOX_TIMER.with(|timer| {
+        let mut timer = timer.borrow_mut();
+        if let Some((at, timer_id)) = *timer {
+            if Some(at) == next {
+                return;
+            }
+            ic_cdk_timers::clear_timer(timer_id);
+            *timer = None;
+        }
+        
+        if let Some(at) = next {
+            let delay = Duration::from_nanos(at.saturating_sub(ic_cdk::api::time()));
+            let timer_id = ic_cdk_timers::set_timer(delay, || {
+                OUTBOX_TIMER.with(|timer| *timer.borrow_mut() = None);
+                ic_cdk::futures::spawn(async {
+                    process_due_outbox(MAX_OUTBOX_BATCH).await;
+                    schedule_outbox_retry();
+                });
+            });
+            *timer = Some((at, timer_id));
+        }
+    });
+}
+
+/// Arms a one-shot timer for the earliest reserve release, replacing any later one.
+/// Holds on disputed entries wait until the dispute is resolved.
+fn schedule_reserve_release() {
+    let next = RESERVE_HOLDS.with(|holds| {
+        holds.borrow().iter()
+            .filter(|(revenue_index, _)| !entry_disputed(*revenue_index))
+            .map(|(_, hold)| hold.release_at)
+            .min()
+    });
+    
+    RESERVE_TIMER.with(|timer| {
+        let mut timer = timer.borrow_mut();
+        if let Some((at, timer_id)) = *timer {
+            if Some(at) == next {
+                return;
+            }
+            ic_cdk_timers::clear_timer(timer_id);
+            *timer = None;
+        }
+        
+        if let Some(at) = next {
+            let delay = Duration::from_nanos(at.saturating_sub(ic_cdk::api::time()));
+            let timer_id = ic_cdk_timers::set_timer(delay, || {
+                RESERVE_TIMER.with(|timer| *timer.borrow_mut() = None);
+                release_due_reserve(reserve::MAX_RELEASE_BATCH);
+                schedule_reserve_release();
+            });
+            *timer = Some((at, timer_id));
+        }
+    });
+}
+
+/// Records a payout of `amount` gross, of which `fee` went to the protocol.
+fn record_claimed(amount: u64, fee: u64, memo: String) {
+    VAULT_STATE.with(|state_ref| {
+        if let Some(ref mut state) = *state_ref.borrow_mut() {
+            state.total_claimed += amount;
+            state.fee_totals.payout += fee;
+            post_journal(state, journal::payout(memo, ic_cdk::api::time(), amount, fee));
+        }
+    });
+    snapshot_payout(ic_cdk::api::time(), amount, fee);
+}
+
+fn payout_fee(amount: u64) -> u64 {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref()
+            .map(|s| shares::apply_bps(amount, s.protocol_fees.payout_fee_bps))
+            .unwrap_or(0)
+    })
+}
+
+fn tranche_rates(state: &VaultState) -> Vec<u128> {
+    state.tranches.iter().map(|t| t.revenue_per_unit).collect()
+}
+
+fn get_tranche_rates() -> Vec<u128> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref()
+            .map(tranche_rates)
+            .unwrap_or_default()
+    })
+}
+
+/// Replaces the waterfall. Only the creator can do this, and only before anyone invests.
+#[update]
+fn set_waterfall(config: WaterfallConfig) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if state.creator != caller {
+                return Err("Only creator can configure the waterfall".to_string());
+            }
+            
+            if state.status != VaultStatus::Funding || state.current_funding > 0 {
+                return Err("Waterfall cannot be changed after investments".to_string());
+            }
+            
+            config.validate(state.funding_goal, state.revenue_share_percentage)?;
+            
+            state.tranches = vec![TrancheState::default(); config.tranches.len()];
+            state.waterfall = config;
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+/// Runs `job` every `interval_seconds`, or stops it when `None`. The creator or the DAO
+/// can do this.
+#[update]
+fn set_schedule(job: ScheduledJob, interval_seconds: Option<u64>) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    
+    if interval_seconds.is_some_and(|interval| interval < schedule::MIN_INTERVAL_SECONDS) {
+        return Err(format!("Schedules cannot run more often than every {} seconds", schedule::MIN_INTERVAL_SECONDS));
+    }
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if caller != state.creator && Some(caller) != state.dao_canister {
+                return Err("Only creator or DAO can change schedules".to_string());
+            }
+            
+            if job == ScheduledJob::OraclePull && state.oracle_canister.is_none() {
+                return Err("Oracle canister not configured".to_string());
+            }
+            
+            *schedule_mut(state, job) = interval_seconds.map(Schedule::new);
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    arm_timer(job);
+    Ok(())
+}
+
+fn schedule_mut(state: &mut VaultState, job: ScheduledJob) -> &mut Option<Schedule> {
+    match job {
+        ScheduledJob::Distribution => &mut state.distribution_schedule,
+        ScheduledJob::OraclePull => &mut state.oracle_schedule,
+    }
+}
+
+/// Replaces any timer for `job` with one matching its persisted schedule.
+fn arm_timer(job: ScheduledJob) {
+    if let Some(timer_id) = TIMERS.with(|timers| timers.borrow_mut().remove(&job)) {
+        ic_cdk_timers::clear_timer(timer_id);
+    }
+    
+    let now = ic_cdk::api::time();
+    let interval = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        let schedule = schedule_mut(state_opt.as_mut()?, job).as_mut()?;
+        schedule.next_run = Some(now.saturating_add(schedule.interval_nanos()));
+        Some(schedule.interval_seconds)
+    });
+    
+    if let Some(interval) = interval {
+        let timer_id = ic_cdk_timers::set_timer_interval(Duration::from_secs(interval), move || {
+            ic_cdk::futures::spawn(run_job(job));
+        });
+        TIMERS.with(|timers| timers.borrow_mut().insert(job, timer_id));
+    }
+}
+
+async fn run_job(job: ScheduledJob) {
+    let started = ic_cdk::api::time();
+    let result = match job {
+        ScheduledJob::Distribution => run_distribution().await,
+        ScheduledJob::OraclePull => pull_oracle_revenue().await,
+    };
+    
+    if let Err(ref e) = result {
+        ic_cdk::println!("Scheduled {:?} failed: {}", job, e);
+    }
+    
+    VAULT_STATE.with(|state_ref| {
+        if let Some(ref mut state) = *state_ref.borrow_mut() {
+            if let Some(schedule) = schedule_mut(state, job) {
+                schedule.record(started, result);
+            }
+        }
+    });
+}
+
+/// Pays out batches until every backer has been visited or the per-run limit is hit.
+async fn run_distribution() -> Result<String, String> {
+    let mut payees = 0;
+    let mut paid = 0;
+    
+    for _ in 0..MAX_BATCHES_PER_RUN {
+        let batch = distribute_batch(MAX_PAYOUT_BATCH).await.map_err(|e| e.message())?;
+        payees += batch.payouts.len();
+        paid += batch.payouts.iter().map(|(_, amount)| amount).sum::<u64>();
+        if batch.complete {
+            return Ok(format!("Paid {} to {} payees", paid, payees));
+        }
+    }
+    
+    Ok(format!("Paid {} to {} payees; distribution continues next run", paid, payees))
+}
+
+/// Asks the oracle to fetch this campaign's revenue; it reports back via `update_revenue`.
+async fn pull_oracle_revenue() -> Result<String, String> {
+    check_not_paused(Operation::RevenueIngestion).map_err(|e| e.message())?;
+    let (campaign_id, oracle) = VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref()
+            .map(|s| (s.campaign_id, s.oracle_canister))
+            .ok_or_else(|| "Vault not initialized".to_string())
+    })?;
+    let oracle = oracle.ok_or_else(|| "Oracle canister not configured".to_string())?;
+    
+    let result: Result<Result<Vec<candid::Reserved>, String>, _> = call(
+        oracle,
+        "fetch_revenue_data",
+        (campaign_id,),
+    ).await;
+    
+    match result {
+        Ok(Ok(records)) => Ok(format!("Oracle fetched {} revenue records", records.len())),
+        Ok(Err(e)) => Err(e),
+        Err(e) => Err(format!("Failed to call oracle: {:?}", e)),
+    }
+}
+
+/// Sets the protocol fee rates and treasury. Only the DAO can do this.
+#[update]
+fn set_protocol_fees(protocol_fees: ProtocolFees) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    
+    protocol_fees.validate()?;
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if Some(caller) != state.dao_canister {
+                return Err("Only the DAO can set protocol fees".to_string());
+            }
+            
+            state.protocol_fees = protocol_fees;
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+/// Pauses or resumes `operations`. See `pause` for who may do which.
+#[update]
+fn set_paused(operations: Vec<Operation>, paused: bool, reason: String) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    let now = ic_cdk::api::time();
+    
+    let events = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            let role = if Some(caller) == state.dao_canister {
+                PauseRole::Dao
+            } else if Some(caller) == state.guardian {
+                PauseRole::Guardian
+            } else if caller == state.creator {
+                PauseRole::Creator
+            } else {
+                return Err("Only the creator, DAO or guardian can pause the vault".to_string());
+            };
+            
+            let mut events = Vec::new();
+            for operation in operations {
+                match (paused, state.paused.get(&operation)) {
+                    (true, Some(_)) | (false, None) => continue,
+                    (false, Some(info)) if !pause::can_unpause(role, info) => {
+                        return Err(format!("{:?} was paused by the {:?} and cannot be resumed by the {:?}", operation, info.role, role));
+                    }
+                    (true, None) => {
+                        state.paused.insert(operation, PauseInfo { by: caller, role, reason: reason.clone(), since: now });
+                    }
+                    (false, Some(_)) => {
+                        state.paused.remove(&operation);
+                    }
+                }
+                events.push(PauseEvent { operation, paused, by: caller, role, reason: reason.clone(), timestamp: now });
+            }
+            Ok(events)
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    PAUSE_HISTORY.with(|history| {
+        let history = history.borrow();
+        for event in &events {
+            history.append(event)
+                .unwrap_or_else(|e| ic_cdk::trap(format!("Failed to record pause: {:?}", e)));
+        }
+    });
+    
+    // Payouts held in the outbox become due again
+    if !paused {
+        schedule_outbox_retry();
+    }
+    
+    Ok(())
+}
+
+/// Sets the guardian that can pause the vault alongside the creator and the DAO.
+/// Only the DAO can do this.
+#[update]
+fn set_guardian(guardian: Option<Principal>) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if Some(caller) != state.dao_canister {
+                return Err("Only the DAO can set the guardian".to_string());
+            }
+            
+            state.guardian = guardian;
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+/// Fails with `OperationError::Paused` while `operation` is paused.
+fn check_not_paused(operation: Operation) -> Result<(), OperationError> {
+    VAULT_STATE.with(|state_ref| {
+        match state_ref.borrow().as_ref().and_then(|s| s.paused.get(&operation)) {
+            Some(info) => Err(OperationError::paused(operation, info)),
+            None => Ok(()),
+        }
+    })
+}
+
+/// Sets the rate source and payout token revenue is converted to. Only the DAO can do
+/// this, and the payout token cannot change once revenue has been recorded.
+#[update]
+fn set_currency_config(currency_config: CurrencyConfig) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    
+    currency_config.validate()?;
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if Some(caller) != state.dao_canister {
+                return Err("Only the DAO can set the currency configuration".to_string());
+            }
+            
+            let current = state.currency_config.payout_token.as_ref().map(|t| (t.symbol.to_uppercase(), t.decimals));
+            let requested = currency_config.payout_token.as_ref().map(|t| (t.symbol.to_uppercase(), t.decimals));
+            if state.total_revenue > 0 && current != requested {
+                return Err("Payout token cannot change once revenue has been recorded".to_string());
+            }
+            
+            state.currency_config = currency_config;
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+/// Sets the share of backer revenue held in reserve and for how long. Only the DAO can
+/// do this; revenue already recorded keeps the terms it was held under.
+#[update]
+fn set_reserve_config(reserve_config: ReserveConfig) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    
+    reserve_config.validate()?;
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if Some(caller) != state.dao_canister {
+                return Err("Only the DAO can set the reserve".to_string());
+            }
+            
+            state.reserve_config = reserve_config;
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+/// Sets the creator's cut of the backers' revenue share. Only the creator can do this,
+/// and only before anyone invests.
+#[update]
+fn set_creator_fee(creator_fee_bps: u64) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    
+    if creator_fee_bps > fees::MAX_FEE_BPS {
+        return Err(format!("Fee rates cannot exceed {} bps", fees::MAX_FEE_BPS));
+    }
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if state.creator != caller {
+                return Err("Only creator can set the creator fee".to_string());
+            }
+            
+            if state.status != VaultStatus::Funding || state.current_funding > 0 {
+                return Err("Creator fee cannot be changed after investments".to_string());
+            }
+            
+            state.creator_fee_bps = creator_fee_bps;
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+/// Sends protocol fees owed to the treasury: raise fees from escrow over the ledger,
+/// revenue and payout fees as a payout stream. Returns the amounts sent for each.
+#[update]
+async fn collect_fees() -> Result<(u64, u64), OperationError> {
+    check_not_paused(Operation::Distribution)?;
+    let _guard = Guard::acquire(Lock::FeeCollection)?;
+    
+    // Reserve everything owed before the awaits; each part is rolled back on its own failure
+    let (ledger, treasury, escrow_owed, revenue_owed) = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            let treasury = state.protocol_fees.treasury
+                .ok_or_else(|| "Treasury not configured".to_string())?;
+            
+            let escrow_owed = state.fee_totals.escrow_owed();
+            let revenue_owed = state.fee_totals.revenue_owed();
+            state.fee_totals.escrow_collected += escrow_owed;
+            state.fee_totals.revenue_collected += revenue_owed;
+            
+            Ok((state.ledger_canister, treasury, escrow_owed, revenue_owed))
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    let mut escrow_sent = 0;
+    if escrow_owed > 0 {
+        let result = match ledger {
+            Some(ledger) => match ledger::fee(ledger).await {
+                Ok(fee) if escrow_owed > fee => {
+                    let memo = get_campaign_id().to_be_bytes().to_vec();
+                    ledger::transfer(ledger, treasury, escrow_owed - fee, memo).await
+                }
+                Ok(_) => Err("Raise fees do not cover the ledger fee yet".to_string()),
+                Err(e) => Err(e),
+            },
+            None => Err("Ledger canister not configured".to_string()),
+        };
+        
+        match result {
+            Ok(block_index) => {
+                escrow_sent = escrow_owed;
+                post_fee_collection(JournalAccount::Escrow, escrow_owed, format!("raise fees block {}", block_index));
+            }
+            Err(e) => {
+                ic_cdk::println!("Failed to collect raise fees: {}", e);
+                release_fee_collection(escrow_owed, 0);
+            }
+        }
+    }
+    
+    let mut revenue_sent = 0;
+    if revenue_owed > 0 {
+        let id = enqueue(None, Effect::FeeStream { treasury, amount: revenue_owed });
+        match process_outbox_item(id).await {
+            Ok(()) => revenue_sent = revenue_owed,
+            Err(e) => ic_cdk::println!("Failed to collect revenue fees: {}", e),
+        }
+    }
+    
+    Ok((escrow_sent, revenue_sent))
+}
+
+fn post_fee_collection(paid_from: JournalAccount, amount: u64, memo: String) {
+    VAULT_STATE.with(|state_ref| {
+        if let Some(ref mut state) = *state_ref.borrow_mut() {
+            post_journal(state, JournalEntry::new(JournalKind::Fee, memo, ic_cdk::api::time())
+                .debit(JournalAccount::TreasuryPayable, amount)
+                .credit(paid_from, amount));
+        }
+    });
 }
 
-#[init]
-fn init(campaign_id: u64, metadata: CampaignMetadata) {
-    let vault_state = VaultState {
-        campaign_id,
-        creator: metadata.creator,
-        title: metadata.title,
-        funding_goal: metadata.funding_goal,
-        current_funding: 0,
-        revenue_share_percentage: metadata.revenue_share_percentage,
-        total_revenue: 0,
-        oracle_endpoints: metadata.oracle_endpoints,
-        nft_registry_canister: None,
-        stream_canister: None,
-        oracle_canister: None,
-        backers: HashMap::new(),
-        revenue_history: Vec::new(),
-        created_at: ic_cdk::api::time(),
-    };
-    
-    VAULT_STATE.with(|state| {
-        *state.borrow_mut() = Some(vault_state);
+fn release_fee_collection(escrow: u64, revenue: u64) {
+    VAULT_STATE.with(|state_ref| {
+        if let Some(ref mut state) = *state_ref.borrow_mut() {
+            state.fee_totals.escrow_collected -= escrow;
+            state.fee_totals.revenue_collected -= revenue;
+        }
     });
-    
-    ic_cdk::println!("Vault initialized for campaign {}", campaign_id);
 }
 
+/// Sets who may invest and how much. Only the creator can do this, while funding is open.
 #[update]
-async fn invest(amount: u64) -> InvestmentResult {
-    let caller = ic_cdk::caller();
+fn set_investment_rules(investment_rules: InvestmentRules) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    
+    investment_rules.validate()?;
     
     VAULT_STATE.with(|state_ref| {
         let mut state_opt = state_ref.borrow_mut();
         if let Some(ref mut state) = *state_opt {
-           
-            if state.current_funding >= state.funding_goal {
-                return InvestmentResult {
-                    success: false,
-                    nft_token_id: None,
-                    share_percentage: 0.0,
-                    message: "Campaign already fully funded".to_string(),
-                };
+            if state.creator != caller {
+                return Err("Only creator can set investment rules".to_string());
             }
-           
-            let remaining_funding = state.funding_goal - state.current_funding;
-            let actual_investment = amount.min(remaining_funding);
-            let share_percentage = (actual_investment as f64 / state.funding_goal as f64) * 100.0;
-            
-         
-            state.current_funding += actual_investment;
-            
-          
-            let backer_info = BackerInfo {
-                amount_invested: actual_investment,
-                nft_token_id: None,
-                share_percentage,
-                total_claimed: 0,
-                investment_timestamp: ic_cdk::api::time(),
-            };
-            
-            state.backers.insert(caller, backer_info.clone());
             
-            InvestmentResult {
-                success: true,
-                nft_token_id: None, 
-                share_percentage,
-                message: format!("Investment of {} successful", actual_investment),
+            if state.status != VaultStatus::Funding {
+                return Err("Investment rules cannot be changed after funding".to_string());
             }
+            
+            state.investment_rules = investment_rules;
+            Ok(())
         } else {
-            InvestmentResult {
-                success: false,
-                nft_token_id: None,
-                share_percentage: 0.0,
-                message: "Vault not initialized".to_string(),
-            }
+            Err("Vault not initialized".to_string())
         }
     })
 }
 
+/// Allowlists or denylists principals; `None` removes a principal from both lists.
 #[update]
-async fn mint_nft_for_backer(backer: Principal) -> Result<u64, String> {
-   
-    let backer_info = VAULT_STATE.with(|state_ref| {
-        let state_opt = state_ref.borrow();
-        if let Some(ref state) = *state_opt {
-            state.backers.get(&backer).cloned()
-        } else {
-            None
+fn update_access_list(changes: Vec<(Principal, Option<AccessLevel>)>) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    
+    let is_creator = VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().map(|s| s.creator == caller)
+    }).ok_or_else(|| "Vault not initialized".to_string())?;
+    
+    if !is_creator {
+        return Err("Only creator can update the access list".to_string());
+    }
+    
+    ACCESS_LIST.with(|list| {
+        let mut list = list.borrow_mut();
+        for (principal, access) in changes {
+            match access {
+                Some(access) => list.insert(principal, access),
+                None => list.remove(&principal),
+            };
         }
     });
     
-    if let Some(info) = backer_info {
-        
-        if let Some(nft_registry) = get_nft_registry_canister() {
-            let metadata = format!(
-                "{{\"campaign_id\":{},\"investment\":{},\"share\":{:.2}}}",
-                get_campaign_id(),
-                info.amount_invested,
-                info.share_percentage
-            );
-            
-            let result: CallResult<(Result<u64, String>,)> = call(
-                nft_registry,
-                "mint",
-                (backer, metadata),
-            ).await;
-            
-            match result {
-                Ok((Ok(token_id),)) => {
-            
-                    VAULT_STATE.with(|state_ref| {
-                        let mut state_opt = state_ref.borrow_mut();
-                        if let Some(ref mut state) = *state_opt {
-                            if let Some(ref mut backer_info) = state.backers.get_mut(&backer) {
-                                backer_info.nft_token_id = Some(token_id);
-                            }
-                        }
-                    });
-                    Ok(token_id)
-                },
-                Ok((Err(e),)) => Err(e),
-                Err(e) => Err(format!("Failed to call NFT registry: {:?}", e)),
-            }
-        } else {
-            Err("NFT registry not configured".to_string())
-        }
-    } else {
-        Err("Backer not found".to_string())
-    }
+    Ok(())
 }
 
+/// Sets the revenue-share term. Only the creator can do this, and only before anyone invests.
 #[update]
-fn update_revenue(amount: u64, source: String, verified: bool) -> Result<(), String> {
+fn set_revenue_term(term: RevenueTerm) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
     
-    let caller = ic_cdk::caller();
+    if term.duration == Some(0) || term.revenue_cap_bps == Some(0) {
+        return Err("Term duration and revenue cap must be greater than 0".to_string());
+    }
     
     VAULT_STATE.with(|state_ref| {
         let mut state_opt = state_ref.borrow_mut();
         if let Some(ref mut state) = *state_opt {
-           
-            
-            let revenue_update = RevenueUpdate {
-                amount,
-                source,
-                timestamp: ic_cdk::api::time(),
-                oracle_verification: verified,
-            };
+            if state.creator != caller {
+                return Err("Only creator can set the revenue term".to_string());
+            }
             
-            state.total_revenue += amount;
-            state.revenue_history.push(revenue_update);
+            if state.status != VaultStatus::Funding || state.current_funding > 0 {
+                return Err("Revenue term cannot be changed after investments".to_string());
+            }
             
-            ic_cdk::println!("Revenue updated: {} from {}", amount, source);
+            state.term = term;
             Ok(())
         } else {
             Err("Vault not initialized".to_string())
@@ -217,57 +3270,43 @@ fn update_revenue(amount: u64, source: String, verified: bool) -> Result<(), Str
     })
 }
 
-#[update]
-async fn distribute_payouts() -> Result<Vec<(Principal, u64)>, String> {
-    let mut payouts = Vec::new();
-    
+/// Shows how `revenue` reported now would flow through the waterfall, after fees.
+#[query]
+fn preview_waterfall(revenue: u64) -> Option<WaterfallPreview> {
     VAULT_STATE.with(|state_ref| {
-        let state_opt = state_ref.borrow();
-        if let Some(ref state) = *state_opt {
-            let distributable_revenue = (state.total_revenue * state.revenue_share_percentage as u64) / 100;
+        state_ref.borrow().as_ref().map(|state| {
+            let gross_pool = backer_pool_delta(state, revenue);
+            let backer_pool = gross_pool
+                - shares::apply_bps(gross_pool, state.protocol_fees.revenue_fee_bps)
+                - shares::apply_bps(gross_pool, state.creator_fee_bps);
+            let (amounts, retained) = state.waterfall.distribute(&state.tranches, backer_pool);
             
-            for (backer, info) in &state.backers {
-                let backer_share = (distributable_revenue as f64 * info.share_percentage / 100.0) as u64;
-                let claimable = backer_share - info.total_claimed;
-                
-                if claimable > 0 {
-                    payouts.push((*backer, claimable));
-                }
+            WaterfallPreview {
+                backer_pool,
+                tranche_amounts: state.waterfall.tranches.iter()
+                    .map(|t| t.name.clone())
+                    .zip(amounts)
+                    .collect(),
+                retained,
             }
-        }
-    });
-    
-    
-    if let Some(stream_canister) = get_stream_canister() {
-        let result: CallResult<(Result<(), String>,)> = call(
-            stream_canister,
-            "create_streams",
-            (payouts.clone(),),
-        ).await;
-        
-        match result {
-            Ok((Ok(()),)) => {
-                
-                VAULT_STATE.with(|state_ref| {
-                    let mut state_opt = state_ref.borrow_mut();
-                    if let Some(ref mut state) = *state_opt {
-                        for (backer, amount) in &payouts {
-                            if let Some(ref mut info) = state.backers.get_mut(backer) {
-                                info.total_claimed += amount;
-                            }
-                        }
-                    }
-                });
-                Ok(payouts)
-            },
-            Ok((Err(e),)) => Err(e),
-            Err(e) => Err(format!("Failed to create streams: {:?}", e)),
-        }
-    } else {
-        Err("Stream canister not configured".to_string())
-    }
+        })
+    })
+}
+
+fn get_backer(backer: &Principal) -> Option<BackerInfo> {
+    BACKERS.with(|backers| backers.borrow().get(backer))
 }
 
+/// Applies `f` to a stored backer and writes the result back to stable memory.
+fn update_backer<R>(backer: &Principal, f: impl FnOnce(&mut BackerInfo) -> R) -> Option<R> {
+    BACKERS.with(|backers| {
+        let mut backers = backers.borrow_mut();
+        let mut info = backers.get(backer)?;
+        let result = f(&mut info);
+        backers.insert(*backer, info);
+        Some(result)
+    })
+}
 
 fn get_campaign_id() -> u64 {
     VAULT_STATE.with(|state_ref| {
@@ -287,6 +3326,8 @@ fn get_stream_canister() -> Option<Principal> {
     })
 }
 
+/// Vault configuration and counters. Backers and revenue history are read a page at a
+/// time through `get_backers_page` and `get_revenue_page`.
 #[query]
 fn get_vault_state() -> Option<VaultState> {
     VAULT_STATE.with(|state_ref| {
@@ -296,36 +3337,438 @@ fn get_vault_state() -> Option<VaultState> {
 
 #[query]
 fn get_backer_info(backer: Principal) -> Option<BackerInfo> {
+    get_backer(&backer)
+}
+
+/// Claimable on the lots `backer` invested, whoever holds them now; see `get_position`
+/// for a single position NFT.
+#[query]
+fn get_claimable(backer: Principal) -> u64 {
+    get_backer(&backer)
+        .map(|info| claimable_amount(&info, &get_tranche_rates()))
+        .unwrap_or(0)
+}
+
+/// Backers from the largest position down, up to `limit` per page. Pass the returned
+/// cursor to continue after the last backer of the previous page.
+#[query]
+fn get_backers_page(cursor: Option<BackerCursor>, limit: u32) -> BackerPage {
+    let limit = limit.clamp(1, history::MAX_PAGE_SIZE) as usize;
+    let keys: Vec<(u64, Principal)> = BACKERS_BY_AMOUNT.with(|index| {
+        let index = index.borrow();
+        match cursor {
+            Some(cursor) => index.range(..(cursor.amount_invested, cursor.backer)).rev().map(|(key, _)| key).take(limit).collect(),
+            None => index.iter().rev().map(|(key, _)| key).take(limit).collect(),
+        }
+    });
+    
+    let next_cursor = if keys.len() == limit {
+        keys.last().map(|(amount_invested, backer)| BackerCursor { amount_invested: *amount_invested, backer: *backer })
+    } else {
+        None
+    };
+    
+    BackerPage {
+        backers: keys.into_iter()
+            .filter_map(|(_, backer)| get_backer(&backer).map(|info| (backer, info)))
+            .collect(),
+        next_cursor,
+    }
+}
+
+/// Revenue entries matching `filter`, in log order, starting at index `start`. A page
+/// may come back short when the scan limit is hit; continue from `next_start`.
+#[query]
+fn get_revenue_page(filter: RevenueFilter, start: Option<u64>, limit: u32) -> RevenuePage {
+    let limit = limit.clamp(1, history::MAX_PAGE_SIZE) as usize;
+    REVENUE_HISTORY.with(|history| {
+        let history = history.borrow();
+        let len = history.len();
+        let timestamp_at = |index| history.get(index).map(|entry| entry.timestamp).unwrap_or(u64::MAX);
+        let first = filter.from.map(|from| history::first_at_or_after(len, from, timestamp_at)).unwrap_or(0);
+        
+        let mut index = start.unwrap_or(0).max(first);
+        let scan_end = index.saturating_add(history::MAX_REVENUE_SCAN).min(len);
+        let mut entries = Vec::new();
+        while index < scan_end && entries.len() < limit {
+            let Some(entry) = history.get(index) else { break };
+            if filter.is_past(&entry) {
+                return RevenuePage { entries, next_start: None };
+            }
+            if filter.matches(&entry) {
+                entries.push((index, entry));
+            }
+            index += 1;
+        }
+        
+        RevenuePage {
+            entries,
+            next_start: if index < len { Some(index) } else { None },
+        }
+    })
+}
+
+/// Revenue totals per calendar month and source, for months `from_month` through
+/// `to_month` inclusive (`YYYYMM`, UTC).
+#[query]
+fn get_monthly_revenue(from_month: Option<u32>, to_month: Option<u32>, source: Option<String>) -> Vec<MonthlyRevenue> {
+    let start = MonthKey { month: from_month.unwrap_or(0), source: String::new() };
+    let to_month = to_month.unwrap_or(u32::MAX);
+    MONTHLY_REVENUE.with(|monthly| {
+        monthly.borrow().range(start..)
+            .map(|(_, totals)| totals)
+            .take_while(|totals| totals.month <= to_month)
+            .filter(|totals| source.as_ref().is_none_or(|source| *source == totals.source))
+            .take(history::MAX_PAGE_SIZE as usize)
+            .collect()
+    })
+}
+
+/// Revenue, fees, backer credits and payouts recorded in each month or quarter from
+/// `from_month` through `to_month` (`YYYYMM`, UTC).
+#[query]
+fn get_period_summaries(from_month: Option<u32>, to_month: Option<u32>, granularity: Granularity) -> Vec<PeriodSummary> {
+    let snapshots = period_snapshots(from_month.unwrap_or(0), to_month.unwrap_or(u32::MAX));
+    statements::summarize(snapshots, granularity)
+}
+
+#[query]
+fn get_period_snapshot(month: u32) -> Option<PeriodSnapshot> {
+    PERIOD_SNAPSHOTS.with(|snapshots| snapshots.borrow().get(&month))
+}
+
+/// Investments, position transfers and the monthly revenue earned on each lot while
+/// `backer` held it, from `from_month` through `to_month` (`YYYYMM`, UTC), with a CSV
+/// export.
+#[query]
+fn get_backer_statement(backer: Principal, from_month: u32, to_month: u32) -> Result<BackerStatement, String> {
+    statements::validate_range(from_month, to_month)?;
+    
+    let statement_lot = |investor: Principal, lot_index: u32, lot: InvestmentLot| StatementLot {
+        investor,
+        lot_index,
+        owners: lot.nft_token_id.map(position_owners).unwrap_or_default(),
+        lot,
+    };
+    let mut lots: Vec<StatementLot> = get_backer(&backer)
+        .map(|info| {
+            info.lots.into_iter().enumerate()
+                .map(|(lot_index, lot)| statement_lot(backer, lot_index as u32, lot))
+                .collect()
+        })
+        .unwrap_or_default();
+    
+    let held: BTreeSet<u64> = POSITION_OWNERS.with(|owners| {
+        owners.borrow().iter()
+            .filter(|(_, record)| record.owner == backer)
+            .map(|((token_id, _), _)| token_id)
+            .collect()
+    });
+    for token_id in held {
+        let Some((investor, lot_index)) = POSITIONS.with(|positions| positions.borrow().get(&token_id)) else {
+            continue;
+        };
+        if investor == backer {
+            continue;
+        }
+        if let Some(lot) = get_backer(&investor).and_then(|info| info.lots.get(lot_index as usize).cloned()) {
+            lots.push(statement_lot(investor, lot_index, lot));
+        }
+    }
+    
+    if lots.is_empty() {
+        return Err("Never held a position in this vault".to_string());
+    }
+    let payout_unit = VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref()
+            .and_then(|s| s.currency_config.payout_token.as_ref().map(|token| token.symbol.to_uppercase()))
+            .unwrap_or_else(|| "payout".to_string())
+    });
+    
+    let snapshots = period_snapshots(from_month, to_month);
+    Ok(statements::backer_statement(backer, &lots, from_month, to_month, &snapshots, &payout_unit))
+}
+
+fn period_snapshots(from_month: u32, to_month: u32) -> Vec<PeriodSnapshot> {
+    PERIOD_SNAPSHOTS.with(|snapshots| {
+        snapshots.borrow().range(from_month..=to_month)
+            .map(|(_, snapshot)| snapshot)
+            .take(history::MAX_PAGE_SIZE as usize)
+            .collect()
+    })
+}
+
+/// Rounding dust per tranche: revenue credited to the tranche that no lot is owed,
+/// because each lot's share is rounded down. It stays in the vault, and later revenue
+/// can lift lots past the next whole unit and pay part of it out.
+#[query]
+fn get_revenue_dust() -> Vec<(String, u64)> {
+    let (names, tranches): (Vec<String>, Vec<TrancheState>) = VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref()
+            .map(|s| (s.waterfall.tranches.iter().map(|t| t.name.clone()).collect(), s.tranches.clone()))
+            .unwrap_or_default()
+    });
+    
+    let mut units: Vec<Vec<u64>> = vec![Vec::new(); tranches.len()];
+    BACKERS.with(|backers| {
+        for (_, info) in backers.borrow().iter() {
+            for lot in &info.lots {
+                if let Some(units) = units.get_mut(lot.tranche as usize) {
+                    units.push(lot.amount);
+                }
+            }
+        }
+    });
+    
+    names.into_iter()
+        .zip(tranches.iter().zip(units))
+        .map(|(name, (tranche, units))| {
+            let dust = shares::dust(tranche.revenue_per_unit, tranche.revenue_per_unit_carry, units, tranche.raised);
+            (name, dust)
+        })
+        .collect()
+}
+
+/// Reserve holds from revenue entry `start` on, oldest first.
+#[query]
+fn get_reserve_holds(start: u64, limit: u32) -> Vec<ReserveHold> {
+    RESERVE_HOLDS.with(|holds| {
+        holds.borrow().range(start..)
+            .map(|(_, hold)| hold)
+            .take(limit.clamp(1, history::MAX_PAGE_SIZE) as usize)
+            .collect()
+    })
+}
+
+#[query]
+fn get_paused() -> Vec<(Operation, PauseInfo)> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref()
+            .map(|s| s.paused.iter().map(|(operation, info)| (*operation, info.clone())).collect())
+            .unwrap_or_default()
+    })
+}
+
+#[query]
+fn get_pause_history(start: u64, len: u64) -> Vec<PauseEvent> {
+    PAUSE_HISTORY.with(|pauses| {
+        let pauses = pauses.borrow();
+        (start..start.saturating_add(len.min(history::MAX_PAGE_SIZE as u64)).min(pauses.len()))
+            .filter_map(|index| pauses.get(index))
+            .collect()
+    })
+}
+
+#[query]
+fn get_revenue_event(event_key: RevenueEventKey) -> Option<RecordedEvent> {
+    REVENUE_EVENTS.with(|events| events.borrow().get(&event_key))
+}
+
+#[query]
+fn get_revenue_by_currency() -> Vec<(String, CurrencyTotal)> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref()
+            .map(|s| s.revenue_by_currency.iter().map(|(currency, total)| (currency.clone(), total.clone())).collect())
+            .unwrap_or_default()
+    })
+}
+
+#[query]
+fn get_schedule_status() -> ScheduleStatus {
+    VAULT_STATE.with(|state_ref| {
+        let state = state_ref.borrow();
+        ScheduleStatus {
+            distribution: state.as_ref().and_then(|s| s.distribution_schedule.clone()),
+            oracle_pull: state.as_ref().and_then(|s| s.oracle_schedule.clone()),
+        }
+    })
+}
+
+#[query]
+fn get_journal(start: u64, len: u64) -> Vec<JournalEntry> {
+    JOURNAL.with(|journal| {
+        let journal = journal.borrow();
+        (start..start.saturating_add(len.min(MAX_JOURNAL_PAGE)).min(journal.len()))
+            .filter_map(|index| journal.get(index))
+            .collect()
+    })
+}
+
+/// Reconciles the journal with the vault's counters and with its balance on the ledger.
+#[query(composite = true)]
+async fn check_invariants() -> Result<InvariantReport, String> {
+    let state = get_vault_state().ok_or_else(|| "Vault not initialized".to_string())?;
+    let ledger = state.ledger_canister.ok_or_else(|| "Ledger canister not configured".to_string())?;
+    let ledger_balance = ledger::balance_of(ledger, ic_cdk::api::canister_self()).await?;
+    
+    let balance = |account| state.journal_balances.get(&account).copied().unwrap_or(0);
+    let mut violations = Vec::new();
+    
+    let net: i128 = state.journal_balances.values().sum();
+    if net != 0 {
+        violations.push(format!("Journal does not balance: net {}", net));
+    }
+    
+    let capital = state.current_funding as i128 - state.total_withdrawn as i128 - state.total_refunded as i128;
+    if -balance(JournalAccount::BackerCapital) != capital {
+        violations.push(format!(
+            "Backer capital {} does not match funding counters {}",
+            -balance(JournalAccount::BackerCapital), capital
+        ));
+    }
+    
+    let payable = state.total_distributable as i128 - state.total_claimed as i128;
+    if -balance(JournalAccount::BackerPayable) != payable {
+        violations.push(format!(
+            "Backer payable {} does not match distribution counters {}",
+            -balance(JournalAccount::BackerPayable), payable
+        ));
+    }
+    
+    if -balance(JournalAccount::Reserve) != state.reserve_totals.held as i128 {
+        violations.push(format!(
+            "Reserve {} does not match reserve counters {}",
+            -balance(JournalAccount::Reserve), state.reserve_totals.held
+        ));
+    }
+    
+    let fees_owed = state.fee_totals.escrow_owed() as i128 + state.fee_totals.revenue_owed() as i128;
+    if -balance(JournalAccount::TreasuryPayable) != fees_owed {
+        violations.push(format!(
+            "Treasury payable {} does not match fee counters {}",
+            -balance(JournalAccount::TreasuryPayable), fees_owed
+        ));
+    }
+    
+    if balance(JournalAccount::Escrow) > ledger_balance as i128 {
+        violations.push(format!(
+            "Escrow {} exceeds ledger balance {}",
+            balance(JournalAccount::Escrow), ledger_balance
+        ));
+    }
+    
+    Ok(InvariantReport {
+        balances: state.journal_balances.into_iter().collect(),
+        ledger_balance,
+        violations,
+    })
+}
+
+#[query]
+fn get_fee_accounting() -> Option<FeeAccounting> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().map(|state| FeeAccounting {
+            protocol_fees: state.protocol_fees.clone(),
+            creator_fee_bps: state.creator_fee_bps,
+            totals: state.fee_totals.clone(),
+            escrow_owed: state.fee_totals.escrow_owed(),
+            revenue_owed: state.fee_totals.revenue_owed(),
+        })
+    })
+}
+
+#[query]
+fn get_access_level(principal: Principal) -> Option<AccessLevel> {
+    ACCESS_LIST.with(|list| list.borrow().get(&principal))
+}
+
+#[query]
+fn get_milestones() -> Vec<Milestone> {
     VAULT_STATE.with(|state_ref| {
-        state_ref.borrow().as_ref().and_then(|s| s.backers.get(&backer).cloned())
+        state_ref.borrow().as_ref().map(|s| s.milestones.clone()).unwrap_or_default()
+    })
+}
+
+/// The lot behind a position NFT and what its current holder can claim.
+#[query]
+fn get_position(token_id: u64) -> Option<PositionInfo> {
+    let (investor, lot_index) = POSITIONS.with(|positions| positions.borrow().get(&token_id))?;
+    let lot = get_backer(&investor)?.lots.get(lot_index as usize)?.clone();
+    
+    Some(PositionInfo {
+        token_id,
+        investor,
+        lot_index,
+        claimable: lot_claimable(&lot, &get_tranche_rates()),
+        lot,
+    })
+}
+
+/// Owners of record of a position NFT after its investor, oldest first.
+#[query]
+fn get_position_owners(token_id: u64) -> Vec<OwnerRecord> {
+    position_owners(token_id)
+}
+
+/// An outbox item; applied and cancelled items are pruned 30 days after they complete.
+#[query]
+fn get_outbox_item(id: u64) -> Option<OutboxItem> {
+    OUTBOX.with(|outbox| outbox.borrow().get(&id))
+}
+
+/// Pending outbox items that have failed repeatedly and may need attention.
+#[query]
+fn get_stuck_outbox_items() -> Vec<OutboxItem> {
+    OUTBOX_PENDING.with(|pending| {
+        pending.borrow().iter()
+            .filter_map(|((_, id), ())| OUTBOX.with(|outbox| outbox.borrow().get(&id)))
+            .filter(|item| item.is_stuck())
+            .collect()
     })
 }
 
 #[query]
-fn get_funding_progress() -> (u64, u64, f64) {
+fn get_revenue_disputes() -> Vec<RevenueDispute> {
+    REVENUE_DISPUTES.with(|disputes| disputes.borrow().iter().map(|(_, d)| d).collect())
+}
+
+#[query]
+fn get_funding_progress() -> (u64, u64, u64) {
     VAULT_STATE.with(|state_ref| {
         if let Some(ref state) = *state_ref.borrow() {
-            let percentage = (state.current_funding as f64 / state.funding_goal as f64) * 100.0;
-            (state.current_funding, state.funding_goal, percentage)
+            let progress_bps = shares::share_to_bps(shares::share_of(state.current_funding, state.funding_goal));
+            (state.current_funding, state.funding_goal, progress_bps)
         } else {
-            (0, 0, 0.0)
+            (0, 0, 0)
         }
     })
 }
 
+/// Sets the canisters the vault works with. The oracle and the DAO can only be changed
+/// by the factory or the DAO, since they vouch for revenue and rule on the vault. The
+/// ledger, NFT registry and stream canister are set by the creator, factory or DAO and
+/// are fixed once anyone has invested, so funds, positions and payouts cannot be
+/// redirected.
 #[update]
 fn set_canister_refs(
     nft_registry: Option<Principal>,
     stream: Option<Principal>,
     oracle: Option<Principal>,
+    ledger: Option<Principal>,
+    dao: Option<Principal>,
 ) -> Result<(), String> {
-    let caller = ic_cdk::caller();
+    let caller = ic_cdk::api::msg_caller();
     
     VAULT_STATE.with(|state_ref| {
         let mut state_opt = state_ref.borrow_mut();
         if let Some(ref mut state) = *state_opt {
-            if state.creator != caller {
-                return Err("Only creator can set canister references".to_string());
+            let governs = caller == state.factory_canister || Some(caller) == state.dao_canister;
+            if !governs && caller != state.creator {
+                return Err("Only the creator, factory or DAO can set canister references".to_string());
+            }
+            if (oracle.is_some() || dao.is_some()) && !governs {
+                return Err("Only the factory or the DAO can set the oracle and DAO canisters".to_string());
+            }
+            
+            let invested = state.current_funding > 0;
+            for (name, requested, current) in [
+                ("Ledger", ledger, state.ledger_canister),
+                ("NFT registry", nft_registry, state.nft_registry_canister),
+                ("Stream", stream, state.stream_canister),
+            ] {
+                if invested && requested.is_some_and(|requested| current != Some(requested)) {
+                    return Err(format!("{} canister cannot be changed after investments", name));
+                }
             }
             
             if let Some(nft) = nft_registry {
@@ -337,6 +3780,12 @@ fn set_canister_refs(
             if let Some(oracle) = oracle {
                 state.oracle_canister = Some(oracle);
             }
+            if let Some(ledger) = ledger {
+                state.ledger_canister = Some(ledger);
+            }
+            if let Some(dao) = dao {
+                state.dao_canister = Some(dao);
+            }
             
             Ok(())
         } else {
@@ -345,13 +3794,73 @@ fn set_canister_refs(
     })
 }
 
-
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct CampaignMetadata {
     pub creator: Principal,
     pub title: String,
     pub description: String,
     pub funding_goal: u64,
+    pub funding_deadline: u64,
     pub revenue_share_percentage: u8,
     pub oracle_endpoints: Vec<String>,
-}
\ No newline at end of file
+    pub milestones: Vec<MilestoneConfig>,
+    /// Oracle and DAO the factory sets the vault up with
+    #[serde(default)]
+    pub oracle_canister: Option<Principal>,
+    #[serde(default)]
+    pub dao_canister: Option<Principal>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lot(nft_token_id: Option<u64>) -> InvestmentLot {
+        InvestmentLot {
+            amount: 1_000,
+            share: 0,
+            timestamp: 0,
+            ledger_block_index: 0,
+            nft_token_id,
+            tranche: 0,
+            claimed: 0,
+        }
+    }
+
+    #[test]
+    fn unminted_lot_pays_investor() {
+        let investor = Principal::from_slice(&[1]);
+        assert_eq!(lot_payee(investor, &lot(None), &BTreeMap::new()), Some(investor));
+    }
+
+    #[test]
+    fn minted_lot_pays_current_owner() {
+        let (investor, buyer) = (Principal::from_slice(&[1]), Principal::from_slice(&[2]));
+        let owners = BTreeMap::from([(7, buyer)]);
+        assert_eq!(lot_payee(investor, &lot(Some(7)), &owners), Some(buyer));
+    }
+
+    #[test]
+    fn frozen_entry_holds_back_only_its_own_credit() {
+        let mut tranche = TrancheState { raised: 1_000, ..Default::default() };
+        let mut hold = ReserveHold { revenue_index: 1, held: vec![100], release_at: 0 };
+        credit_tranche(&mut tranche, 600);
+        credit_tranche(&mut tranche, 400 - hold.total());
+
+        // Entry 1 credited 400, 100 of it still held; freezing moves the other 300
+        let moved = hold.hold_all(&[400]);
+        debit_tranche(&mut tranche, moved[0]);
+
+        assert_eq!(moved, vec![300]);
+        assert_eq!(hold.total(), 400);
+        assert_eq!(shares::accrued(1_000, tranche.revenue_per_unit), 600);
+    }
+
+    #[test]
+    fn transferred_lot_without_owner_is_skipped() {
+        let (investor, buyer) = (Principal::from_slice(&[1]), Principal::from_slice(&[2]));
+        // The NFT was sold on, but this lookup came back without it
+        let owners = BTreeMap::from([(8, buyer)]);
+        assert_eq!(lot_payee(investor, &lot(Some(7)), &owners), None);
+    }
+}
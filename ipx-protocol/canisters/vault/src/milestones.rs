@@ -0,0 +1,233 @@
+// Milestone-gated release of raised funds to the creator.
+//
+// The creator submits evidence for the next milestone, which opens a review window
+// for backers. Backers vote with the amount they invested: once either side holds a
+// majority of the raised funds the review settles early; otherwise the milestone is
+// approved when the window closes unless more weight voted against it than for it.
+// Each approved milestone releases its share of the raised funds to the creator.
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+use crate::shares::{self, BPS_SCALE};
+
+/// How long backers have to review a milestone's evidence, in nanoseconds (7 days)
+pub const REVIEW_PERIOD: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct MilestoneConfig {
+    pub title: String,
+    pub description: String,
+    /// Share of the raised funds released by this milestone, in basis points
+    pub release_bps: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum MilestoneStatus {
+    Pending,
+    UnderReview,
+    Approved,
+    Rejected,
+    Released,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Milestone {
+    pub title: String,
+    pub description: String,
+    pub release_bps: u64,
+    pub status: MilestoneStatus,
+    pub evidence: Option<String>,
+    pub review_ends_at: Option<u64>,
+    pub votes_for: u64,
+    pub votes_against: u64,
+    pub released_amount: u64,
+    pub release_block_index: Option<u64>,
+}
+
+impl From<MilestoneConfig> for Milestone {
+    fn from(config: MilestoneConfig) -> Self {
+        Milestone {
+            title: config.title,
+            description: config.description,
+            release_bps: config.release_bps,
+            status: MilestoneStatus::Pending,
+            evidence: None,
+            review_ends_at: None,
+            votes_for: 0,
+            votes_against: 0,
+            released_amount: 0,
+            release_block_index: None,
+        }
+    }
+}
+
+impl Milestone {
+    /// Approves or rejects a milestone under review once the vote or the window allows.
+    /// `total_weight` is the voting weight of all backers together.
+    pub fn settle(&mut self, total_weight: u64, now: u64) {
+        if self.status != MilestoneStatus::UnderReview {
+            return;
+        }
+
+        let majority = total_weight / 2;
+        let window_closed = self.review_ends_at.is_some_and(|ends_at| now >= ends_at);
+
+        if self.votes_for > majority {
+            self.status = MilestoneStatus::Approved;
+        } else if self.votes_against > majority {
+            self.status = MilestoneStatus::Rejected;
+        } else if window_closed {
+            self.status = if self.votes_against > self.votes_for {
+                MilestoneStatus::Rejected
+            } else {
+                MilestoneStatus::Approved
+            };
+        }
+    }
+
+    /// Marks an approved milestone released for `amount`. A milestone is released at
+    /// most once.
+    pub fn release(&mut self, amount: u64) -> Result<(), String> {
+        if self.status != MilestoneStatus::Approved {
+            return Err("Milestone has not been approved".to_string());
+        }
+        self.status = MilestoneStatus::Released;
+        self.released_amount = amount;
+        Ok(())
+    }
+}
+
+/// Milestones for a new vault. Without any, all funds are released by a single milestone.
+pub fn build(configs: Vec<MilestoneConfig>) -> Result<Vec<Milestone>, String> {
+    if configs.is_empty() {
+        return Ok(vec![Milestone::from(single_release())]);
+    }
+
+    if configs.iter().any(|m| m.release_bps == 0) {
+        return Err("Milestone release must be greater than 0".to_string());
+    }
+
+    let total_bps: u128 = configs.iter().map(|m| m.release_bps as u128).sum();
+    if total_bps != BPS_SCALE as u128 {
+        return Err("Milestone releases must add up to 100%".to_string());
+    }
+
+    Ok(configs.into_iter().map(Milestone::from).collect())
+}
+
+pub fn single_release() -> MilestoneConfig {
+    MilestoneConfig {
+        title: "Funds release".to_string(),
+        description: "Release of all raised funds".to_string(),
+        release_bps: BPS_SCALE,
+    }
+}
+
+/// Amount each milestone releases out of `raised`. Always sums to exactly `raised`.
+pub fn release_amounts(milestones: &[Milestone], raised: u64) -> Vec<u64> {
+    let weights: Vec<(usize, u64)> = milestones.iter().map(|m| m.release_bps).enumerate().collect();
+    shares::allocate(raised, &weights).into_iter().map(|(_, amount)| amount).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn milestones(release_bps: &[u64]) -> Vec<Milestone> {
+        build(release_bps.iter().map(|&release_bps| MilestoneConfig {
+            title: String::new(),
+            description: String::new(),
+            release_bps,
+        }).collect()).unwrap()
+    }
+
+    fn under_review(ends_at: u64) -> Milestone {
+        Milestone {
+            status: MilestoneStatus::UnderReview,
+            review_ends_at: Some(ends_at),
+            ..Milestone::from(single_release())
+        }
+    }
+
+    #[test]
+    fn releases_sum_to_the_amount_raised() {
+        let milestones = milestones(&[3_333, 3_333, 3_334]);
+        for raised in [0, 1, 2, 10, 9_999, 1_000_001, u64::MAX] {
+            let amounts = release_amounts(&milestones, raised);
+            assert_eq!(amounts.iter().map(|&a| a as u128).sum::<u128>(), raised as u128);
+        }
+    }
+
+    #[test]
+    fn rounding_dust_goes_to_the_largest_remainder() {
+        // 100 * 0.3333 = 33.33 twice and 100 * 0.3334 = 33.34; one unit of dust is left
+        let amounts = release_amounts(&milestones(&[3_333, 3_333, 3_334]), 100);
+        assert_eq!(amounts, vec![33, 33, 34]);
+    }
+
+    #[test]
+    fn build_rejects_releases_not_adding_up() {
+        let config = |release_bps| MilestoneConfig { title: String::new(), description: String::new(), release_bps };
+        assert!(build(vec![config(5_000), config(4_999)]).is_err());
+        assert!(build(vec![config(BPS_SCALE), config(0)]).is_err());
+        assert_eq!(build(Vec::new()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn majority_settles_early() {
+        let mut milestone = under_review(100);
+        milestone.votes_for = 501;
+        milestone.settle(1_000, 0);
+        assert_eq!(milestone.status, MilestoneStatus::Approved);
+
+        let mut milestone = under_review(100);
+        milestone.votes_against = 501;
+        milestone.settle(1_000, 0);
+        assert_eq!(milestone.status, MilestoneStatus::Rejected);
+    }
+
+    #[test]
+    fn open_window_without_majority_stays_under_review() {
+        let mut milestone = under_review(100);
+        milestone.votes_for = 500;
+        milestone.settle(1_000, 99);
+        assert_eq!(milestone.status, MilestoneStatus::UnderReview);
+    }
+
+    #[test]
+    fn closed_window_approves_unless_outvoted() {
+        let mut milestone = under_review(100);
+        milestone.settle(1_000, 100);
+        assert_eq!(milestone.status, MilestoneStatus::Approved);
+
+        let mut milestone = under_review(100);
+        milestone.votes_against = 2;
+        milestone.votes_for = 1;
+        milestone.settle(1_000, 100);
+        assert_eq!(milestone.status, MilestoneStatus::Rejected);
+    }
+
+    #[test]
+    fn settled_milestone_is_released_once() {
+        let mut milestone = under_review(100);
+        milestone.settle(1_000, 100);
+        assert_eq!(milestone.release(600), Ok(()));
+        assert_eq!(milestone.released_amount, 600);
+
+        // Settling again leaves it released, and a second release is refused
+        milestone.settle(1_000, 200);
+        assert_eq!(milestone.status, MilestoneStatus::Released);
+        assert!(milestone.release(600).is_err());
+        assert_eq!(milestone.released_amount, 600);
+    }
+
+    #[test]
+    fn unapproved_milestone_cannot_be_released() {
+        let mut milestone = under_review(100);
+        assert!(milestone.release(600).is_err());
+        milestone.votes_against = 501;
+        milestone.settle(1_000, 0);
+        assert!(milestone.release(600).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+// Persistent outbox for effects on other canisters.
+//
+// Every payout stream, fee stream, position mint and maturity notice is recorded
+// here before the call is made, under an idempotency key the receiving canister uses to recognise
+// retries. An item is applied only once the call is confirmed. A definite rejection
+// cancels it and its local reservation is released; a failed call may or may not
+// have taken effect, so the item stays pending and is retried with exponential
+// backoff under the same key. Applied and cancelled items are kept for a while for
+// inspection and then pruned; item ids are never reused.
+
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+/// First retry delay, in nanoseconds (1 minute); doubles with every attempt
+pub const BASE_BACKOFF: u64 = 60 * 1_000_000_000;
+/// Longest retry delay, in nanoseconds (1 day)
+pub const MAX_BACKOFF: u64 = 24 * 60 * 60 * 1_000_000_000;
+/// Pending items that have failed this many times are reported as stuck
+pub const STUCK_AFTER_ATTEMPTS: u32 = 5;
+/// How long applied and cancelled items are kept before they are pruned, in
+/// nanoseconds (30 days)
+pub const RETENTION: u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum Effect {
+    /// Revenue payouts; `claims` are the lot reservations they settle
+    Payout {
+        payouts: Vec<(Principal, u64)>,
+        claims: Vec<(Principal, u32, u64)>,
+        gross: u64,
+        fee: u64,
+        memo: String,
+    },
+    /// Revenue and payout fees streamed to the treasury
+    FeeStream { treasury: Principal, amount: u64 },
+    MintPosition { backer: Principal, lot_index: u32 },
+    /// Tells the factory and the NFT registry that the revenue-share term has ended
+    NotifyMaturity,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum OutboxStatus {
+    Pending,
+    Applied,
+    Cancelled,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct OutboxItem {
+    pub id: u64,
+    pub idempotency_key: String,
+    pub effect: Effect,
+    pub status: OutboxStatus,
+    pub attempts: u32,
+    pub created_at: u64,
+    pub next_attempt_at: u64,
+    pub last_error: Option<String>,
+    pub completed_at: Option<u64>,
+}
+
+/// How a dispatched call ended.
+pub enum Failure {
+    /// The callee answered with an error; the effect did not happen
+    Rejected(String),
+    /// The call itself failed; the effect may or may not have happened
+    Uncertain(String),
+}
+
+impl OutboxItem {
+    pub fn new(id: u64, idempotency_key: String, effect: Effect, now: u64) -> Self {
+        OutboxItem {
+            id,
+            idempotency_key,
+            effect,
+            status: OutboxStatus::Pending,
+            attempts: 0,
+            created_at: now,
+            next_attempt_at: now,
+            last_error: None,
+            completed_at: None,
+        }
+    }
+
+    pub fn is_stuck(&self) -> bool {
+        self.status == OutboxStatus::Pending && self.attempts >= STUCK_AFTER_ATTEMPTS
+    }
+
+    pub fn complete(&mut self, status: OutboxStatus, error: Option<String>, now: u64) {
+        self.status = status;
+        self.completed_at = Some(now);
+        if error.is_some() {
+            self.last_error = error;
+        }
+    }
+
+    /// Records a failed attempt and pushes the next one back.
+    pub fn retry_later(&mut self, error: String, now: u64) {
+        self.attempts += 1;
+        self.last_error = Some(error);
+        let backoff = BASE_BACKOFF.saturating_mul(1u64 << (self.attempts - 1).min(20)).min(MAX_BACKOFF);
+        self.next_attempt_at = now.saturating_add(backoff);
+    }
+}
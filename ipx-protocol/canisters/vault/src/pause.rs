@@ -0,0 +1,129 @@
+// Emergency pause switches.
+//
+// Each switch halts one kind of operation, e.g. revenue ingestion while an oracle is
+// suspected to be compromised. The creator, the DAO and the vault's guardian can pause
+// anything. Lifting a pause takes the DAO, though the creator may lift a pause the
+// creator set; the guardian can only pull the brake. Paused calls fail with `OperationError::Paused`, and every
+// change is appended to the pause history.
+
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Operation {
+    Investing,
+    RevenueIngestion,
+    /// Payout claims, payout batches and fee collection
+    Distribution,
+    Refunds,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum PauseRole {
+    Creator,
+    Dao,
+    Guardian,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PauseInfo {
+    pub by: Principal,
+    pub role: PauseRole,
+    pub reason: String,
+    pub since: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PauseEvent {
+    pub operation: Operation,
+    pub paused: bool,
+    pub by: Principal,
+    pub role: PauseRole,
+    pub reason: String,
+    pub timestamp: u64,
+}
+
+/// Error from an operation that can be paused.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum OperationError {
+    Paused { operation: Operation, since: u64, reason: String },
+    Failed { message: String },
+}
+
+impl From<String> for OperationError {
+    fn from(message: String) -> Self {
+        OperationError::Failed { message }
+    }
+}
+
+impl OperationError {
+    pub fn paused(operation: Operation, info: &PauseInfo) -> Self {
+        OperationError::Paused {
+            operation,
+            since: info.since,
+            reason: info.reason.clone(),
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            OperationError::Paused { operation, reason, .. } => format!("{:?} is paused: {}", operation, reason),
+            OperationError::Failed { message } => message.clone(),
+        }
+    }
+}
+
+/// Whether `role` may lift a pause set by `info`.
+pub fn can_unpause(role: PauseRole, info: &PauseInfo) -> bool {
+    match role {
+        PauseRole::Dao => true,
+        PauseRole::Guardian => false,
+        PauseRole::Creator => info.role == PauseRole::Creator,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paused_by(role: PauseRole) -> PauseInfo {
+        PauseInfo {
+            by: Principal::anonymous(),
+            role,
+            reason: "incident".to_string(),
+            since: 0,
+        }
+    }
+
+    #[test]
+    fn unpause_rights_by_role() {
+        use PauseRole::*;
+        // (who resumes, who paused, allowed)
+        let cases = [
+            (Dao, Creator, true),
+            (Dao, Dao, true),
+            (Dao, Guardian, true),
+            (Creator, Creator, true),
+            (Creator, Dao, false),
+            (Creator, Guardian, false),
+            (Guardian, Creator, false),
+            (Guardian, Dao, false),
+            (Guardian, Guardian, false),
+        ];
+        for (role, paused_role, allowed) in cases {
+            assert_eq!(
+                can_unpause(role, &paused_by(paused_role)),
+                allowed,
+                "{:?} resuming a pause by {:?}",
+                role,
+                paused_role
+            );
+        }
+    }
+
+    #[test]
+    fn paused_error_carries_the_pause() {
+        let error = OperationError::paused(Operation::Distribution, &paused_by(PauseRole::Guardian));
+        assert_eq!(error.message(), "Distribution is paused: incident");
+    }
+}
@@ -0,0 +1,400 @@
+// Revenue currencies and their conversion to the payout token.
+//
+// Revenue is reported in whatever currency a platform pays in, with its own number
+// of decimals. Before it accrues to backers it is converted to the payout token at
+// the rate given by the configured rate source: any canister implementing the
+// exchange rate canister (XRC) `get_exchange_rate` interface, so a local stub with
+// fixed rates can stand in for the real XRC. Rates older than `MAX_RATE_AGE` are
+// refused. The rate used is kept with each entry.
+
+use candid::{CandidType, Principal};
+use ic_cdk::call::Call;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+
+/// Cycles the XRC charges per `get_exchange_rate` call
+pub const XRC_CALL_CYCLES: u128 = 1_000_000_000;
+/// Most decimals a reported amount or the payout token may use
+pub const MAX_DECIMALS: u8 = 18;
+/// Oldest rate accepted for a conversion, in seconds (1 hour)
+pub const MAX_RATE_AGE: u64 = 60 * 60;
+
+/// Fiat currencies the XRC quotes through its forex sources; anything else is
+/// looked up as a cryptocurrency.
+const FIAT_SYMBOLS: [&str; 12] = ["USD", "EUR", "GBP", "JPY", "CNY", "INR", "CAD", "AUD", "CHF", "KRW", "BRL", "SGD"];
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum AssetClass {
+    Cryptocurrency,
+    FiatCurrency,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Asset {
+    pub symbol: String,
+    pub class: AssetClass,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct GetExchangeRateRequest {
+    pub base_asset: Asset,
+    pub quote_asset: Asset,
+    pub timestamp: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ExchangeRateMetadata {
+    pub decimals: u32,
+    pub base_asset_num_received_rates: u64,
+    pub base_asset_num_queried_sources: u64,
+    pub quote_asset_num_received_rates: u64,
+    pub quote_asset_num_queried_sources: u64,
+    pub standard_deviation: u64,
+    pub forex_timestamp: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ExchangeRate {
+    pub base_asset: Asset,
+    pub quote_asset: Asset,
+    pub timestamp: u64,
+    pub rate: u64,
+    pub metadata: ExchangeRateMetadata,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct OtherError {
+    pub code: u32,
+    pub description: String,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum ExchangeRateError {
+    AnonymousPrincipalNotAllowed,
+    Pending,
+    CryptoBaseAssetNotFound,
+    CryptoQuoteAssetNotFound,
+    StablecoinRateNotFound,
+    StablecoinRateTooFewRates,
+    StablecoinRateZeroRate,
+    ForexInvalidTimestamp,
+    ForexBaseAssetNotFound,
+    ForexQuoteAssetNotFound,
+    ForexAssetsNotFound,
+    RateLimited,
+    NotEnoughCycles,
+    FailedToAcceptCycles,
+    InconsistentRatesReceived,
+    Other(OtherError),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum GetExchangeRateResult {
+    Ok(ExchangeRate),
+    Err(ExchangeRateError),
+}
+
+/// Anything that answers XRC `get_exchange_rate` requests.
+pub trait RateSource {
+    fn get_exchange_rate(&self, request: GetExchangeRateRequest) -> impl Future<Output = Result<GetExchangeRateResult, String>>;
+}
+
+/// A rate source canister, called with the cycles the XRC charges.
+pub struct XrcSource(pub Principal);
+
+impl RateSource for XrcSource {
+    async fn get_exchange_rate(&self, request: GetExchangeRateRequest) -> Result<GetExchangeRateResult, String> {
+        let result: Result<GetExchangeRateResult, ic_cdk::call::Error> = async {
+            let response = Call::unbounded_wait(self.0, "get_exchange_rate")
+                .with_arg(request)
+                .with_cycles(XRC_CALL_CYCLES)
+                .await?;
+            Ok(response.candid()?)
+        }.await;
+
+        result.map_err(|e| format!("Failed to call rate source: {:?}", e))
+    }
+}
+
+/// The token revenue is paid out in, e.g. ckUSDC with 6 decimals.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PayoutToken {
+    pub symbol: String,
+    pub class: AssetClass,
+    pub decimals: u8,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct CurrencyConfig {
+    pub rate_source: Option<Principal>,
+    pub payout_token: Option<PayoutToken>,
+}
+
+/// An amount of revenue as reported, in `currency` with `decimals` decimal places.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RevenueAmount {
+    pub amount: u64,
+    pub currency: String,
+    pub decimals: u8,
+}
+
+/// How a reported amount was converted to the payout token. `rate` is payout token
+/// per unit of `currency`, with `rate_decimals` decimals, as of `rate_timestamp`
+/// (seconds); 1 for revenue reported in the payout token itself.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Conversion {
+    pub reported: RevenueAmount,
+    pub rate: u64,
+    pub rate_decimals: u32,
+    pub rate_timestamp: u64,
+}
+
+/// Lifetime revenue reported in one currency.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct CurrencyTotal {
+    pub decimals: u8,
+    pub reported: u64,
+    /// The same revenue in payout token units
+    pub converted: u64,
+}
+
+impl CurrencyConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(ref token) = self.payout_token {
+            if token.symbol.is_empty() {
+                return Err("Payout token symbol is required".to_string());
+            }
+            if token.decimals > MAX_DECIMALS {
+                return Err(format!("Payout token cannot have more than {} decimals", MAX_DECIMALS));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RevenueAmount {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.currency.is_empty() {
+            return Err("Revenue currency is required".to_string());
+        }
+        if self.decimals > MAX_DECIMALS {
+            return Err(format!("Revenue cannot have more than {} decimals", MAX_DECIMALS));
+        }
+        Ok(())
+    }
+
+    fn asset(&self) -> Asset {
+        let symbol = self.currency.to_uppercase();
+        let class = if FIAT_SYMBOLS.contains(&symbol.as_str()) {
+            AssetClass::FiatCurrency
+        } else {
+            AssetClass::Cryptocurrency
+        };
+        Asset { symbol, class }
+    }
+}
+
+/// `amount` with `from_decimals` decimals, times `rate` with `rate_decimals`
+/// decimals, expressed with `to_decimals` decimals. Rounds down.
+pub fn convert(amount: u64, from_decimals: u8, rate: u64, rate_decimals: u32, to_decimals: u8) -> Result<u64, String> {
+    let overflow = || "Converted revenue is too large".to_string();
+    let value = amount as u128 * rate as u128;
+    let scale_down = from_decimals as u32 + rate_decimals;
+    let scaled = if to_decimals as u32 >= scale_down {
+        10u128.checked_pow(to_decimals as u32 - scale_down)
+            .and_then(|factor| value.checked_mul(factor))
+            .ok_or_else(overflow)?
+    } else {
+        value / 10u128.checked_pow(scale_down - to_decimals as u32).ok_or_else(overflow)?
+    };
+    u64::try_from(scaled).map_err(|_| overflow())
+}
+
+/// Converts `revenue` to the payout token, asking `rate_source` for the rate unless
+/// it is already in the payout token.
+pub async fn to_payout_token<S: RateSource>(
+    rate_source: Option<&S>,
+    payout_token: &PayoutToken,
+    revenue: RevenueAmount,
+    now: u64,
+) -> Result<(u64, Conversion), String> {
+    let base = revenue.asset();
+    let (rate, rate_decimals, rate_timestamp) = if base.symbol == payout_token.symbol.to_uppercase() {
+        (1, 0, now / 1_000_000_000)
+    } else {
+        let rate_source = rate_source.ok_or_else(|| "Rate source not configured".to_string())?;
+        let quote = Asset {
+            symbol: payout_token.symbol.to_uppercase(),
+            class: payout_token.class.clone(),
+        };
+        let exchange_rate = get_exchange_rate(rate_source, base, quote, now).await?;
+        (exchange_rate.rate, exchange_rate.metadata.decimals, exchange_rate.timestamp)
+    };
+
+    let converted = convert(revenue.amount, revenue.decimals, rate, rate_decimals, payout_token.decimals)?;
+    Ok((converted, Conversion {
+        reported: revenue,
+        rate,
+        rate_decimals,
+        rate_timestamp,
+    }))
+}
+
+async fn get_exchange_rate<S: RateSource>(
+    rate_source: &S,
+    base_asset: Asset,
+    quote_asset: Asset,
+    now: u64,
+) -> Result<ExchangeRate, String> {
+    let request = GetExchangeRateRequest {
+        base_asset,
+        quote_asset,
+        timestamp: None,
+    };
+
+    match rate_source.get_exchange_rate(request).await? {
+        GetExchangeRateResult::Ok(rate) if rate.rate == 0 => Err("Rate source returned a zero rate".to_string()),
+        GetExchangeRateResult::Ok(rate) if rate.timestamp.saturating_add(MAX_RATE_AGE) < now / 1_000_000_000 => {
+            Err(format!("Exchange rate from {} is stale", rate.timestamp))
+        }
+        GetExchangeRateResult::Ok(rate) => Ok(rate),
+        GetExchangeRateResult::Err(e) => Err(format!("Exchange rate unavailable: {:?}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    const NOW: u64 = 1_700_000_000 * 1_000_000_000;
+
+    /// Quotes fixed rates, all as of the same time, like a local XRC stub would.
+    struct FixedRateSource {
+        /// Base symbol, quote symbol, rate
+        rates: Vec<(&'static str, &'static str, u64)>,
+        decimals: u32,
+        /// Seconds
+        timestamp: u64,
+    }
+
+    impl RateSource for FixedRateSource {
+        async fn get_exchange_rate(&self, request: GetExchangeRateRequest) -> Result<GetExchangeRateResult, String> {
+            let rate = self.rates.iter()
+                .find(|(base, quote, _)| *base == request.base_asset.symbol && *quote == request.quote_asset.symbol)
+                .map(|(_, _, rate)| *rate);
+
+            Ok(match (rate, &request.base_asset.class) {
+                (Some(rate), _) => GetExchangeRateResult::Ok(ExchangeRate {
+                    base_asset: request.base_asset,
+                    quote_asset: request.quote_asset,
+                    timestamp: self.timestamp,
+                    rate,
+                    metadata: ExchangeRateMetadata {
+                        decimals: self.decimals,
+                        base_asset_num_received_rates: 1,
+                        base_asset_num_queried_sources: 1,
+                        quote_asset_num_received_rates: 1,
+                        quote_asset_num_queried_sources: 1,
+                        standard_deviation: 0,
+                        forex_timestamp: None,
+                    },
+                }),
+                (None, AssetClass::FiatCurrency) => GetExchangeRateResult::Err(ExchangeRateError::ForexBaseAssetNotFound),
+                (None, AssetClass::Cryptocurrency) => GetExchangeRateResult::Err(ExchangeRateError::CryptoBaseAssetNotFound),
+            })
+        }
+    }
+
+    fn stub(rates: Vec<(&'static str, &'static str, u64)>) -> FixedRateSource {
+        FixedRateSource { rates, decimals: 2, timestamp: NOW / 1_000_000_000 }
+    }
+
+    fn usdc() -> PayoutToken {
+        PayoutToken { symbol: "ckUSDC".to_string(), class: AssetClass::Cryptocurrency, decimals: 6 }
+    }
+
+    fn revenue(amount: u64, currency: &str, decimals: u8) -> RevenueAmount {
+        RevenueAmount { amount, currency: currency.to_string(), decimals }
+    }
+
+    /// The stub answers straight away, so its futures complete on the first poll.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        match pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("stub rate source should not wait"),
+        }
+    }
+
+    #[test]
+    fn convert_rounds_down() {
+        assert_eq!(convert(1_999, 2, 1, 0, 0), Ok(19));
+        assert_eq!(convert(1, 6, 999_999, 6, 6), Ok(0));
+        assert_eq!(convert(100_000_000, 8, 6_500_012_345_678, 8, 6), Ok(65_000_123_456));
+    }
+
+    #[test]
+    fn convert_scales_up_exactly() {
+        assert_eq!(convert(5, 0, 1, 0, 6), Ok(5_000_000));
+        assert_eq!(convert(10_050, 2, 108, 2, 6), Ok(108_540_000));
+    }
+
+    #[test]
+    fn convert_rejects_overflow() {
+        assert!(convert(u64::MAX, 0, u64::MAX, 0, 0).is_err());
+        assert!(convert(u64::MAX, 0, 1, 0, MAX_DECIMALS).is_err());
+    }
+
+    #[test]
+    fn converts_at_the_stub_rate() {
+        let source = stub(vec![("EUR", "CKUSDC", 108)]);
+        let (amount, conversion) = block_on(to_payout_token(Some(&source), &usdc(), revenue(10_050, "eur", 2), NOW)).unwrap();
+
+        assert_eq!(amount, 108_540_000);
+        assert_eq!((conversion.rate, conversion.rate_decimals), (108, 2));
+        assert_eq!(conversion.rate_timestamp, NOW / 1_000_000_000);
+    }
+
+    #[test]
+    fn payout_token_needs_no_rate() {
+        let (amount, conversion) = block_on(to_payout_token(None::<&FixedRateSource>, &usdc(), revenue(2_500_000, "ckusdc", 6), NOW)).unwrap();
+
+        assert_eq!(amount, 2_500_000);
+        assert_eq!(conversion.rate, 1);
+    }
+
+    #[test]
+    fn missing_rate_is_an_error() {
+        let source = stub(vec![("EUR", "CKUSDC", 108)]);
+
+        let fiat = block_on(to_payout_token(Some(&source), &usdc(), revenue(100, "GBP", 2), NOW));
+        assert_eq!(fiat.unwrap_err(), "Exchange rate unavailable: ForexBaseAssetNotFound");
+
+        let crypto = block_on(to_payout_token(Some(&source), &usdc(), revenue(100, "ICP", 8), NOW));
+        assert_eq!(crypto.unwrap_err(), "Exchange rate unavailable: CryptoBaseAssetNotFound");
+    }
+
+    #[test]
+    fn missing_rate_source_is_an_error() {
+        let result = block_on(to_payout_token(None::<&FixedRateSource>, &usdc(), revenue(100, "EUR", 2), NOW));
+        assert_eq!(result.unwrap_err(), "Rate source not configured");
+    }
+
+    #[test]
+    fn stale_rate_is_an_error() {
+        let fresh = FixedRateSource { timestamp: NOW / 1_000_000_000 - MAX_RATE_AGE, ..stub(vec![("EUR", "CKUSDC", 108)]) };
+        assert!(block_on(to_payout_token(Some(&fresh), &usdc(), revenue(100, "EUR", 2), NOW)).is_ok());
+
+        let stale = FixedRateSource { timestamp: NOW / 1_000_000_000 - MAX_RATE_AGE - 1, ..stub(vec![("EUR", "CKUSDC", 108)]) };
+        let result = block_on(to_payout_token(Some(&stale), &usdc(), revenue(100, "EUR", 2), NOW));
+        assert!(result.unwrap_err().contains("stale"));
+    }
+
+    #[test]
+    fn zero_rate_is_an_error() {
+        let source = stub(vec![("EUR", "CKUSDC", 0)]);
+        let result = block_on(to_payout_token(Some(&source), &usdc(), revenue(100, "EUR", 2), NOW));
+        assert_eq!(result.unwrap_err(), "Rate source returned a zero rate");
+    }
+}
@@ -0,0 +1,178 @@
+// Reserve buffer on backer revenue.
+//
+// A configurable share of what each revenue entry credits to the tranches is held in
+// the reserve instead of becoming claimable straight away. Once the holdback period
+// has passed without a dispute on the entry, the held amount is released to backers.
+// Freezing an entry moves the rest of its credit into its hold until the dispute is
+// settled. When an entry is reversed, its own held amount is cancelled first; whatever it
+// already released is covered from other entries' holds in the same tranche, oldest
+// first, and only the rest is clawed back from backers' accrued revenue.
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+use crate::shares::{self, BPS_SCALE};
+
+/// Highest share of backer revenue the reserve can hold back (50%)
+pub const MAX_RESERVE_BPS: u64 = 5_000;
+/// Longest holdback period, in nanoseconds (one year)
+pub const MAX_HOLDBACK_PERIOD: u64 = 365 * 24 * 60 * 60 * 1_000_000_000;
+/// Most holds released by a single timer run
+pub const MAX_RELEASE_BATCH: usize = 50;
+
+/// Set by the DAO; applies to revenue recorded from then on.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ReserveConfig {
+    /// Share of each entry's backer credit held back, in basis points
+    pub reserve_bps: u64,
+    /// How long it is held, in nanoseconds
+    pub holdback_period: u64,
+}
+
+/// Lifetime reserve totals.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ReserveTotals {
+    /// Currently held
+    pub held: u64,
+    pub released: u64,
+    /// Held amounts cancelled because their own entry was reversed
+    pub cancelled: u64,
+    /// Held amounts used to cover reversals of other entries
+    pub used: u64,
+}
+
+/// What a revenue entry still holds in the reserve, per tranche.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ReserveHold {
+    pub revenue_index: u64,
+    pub held: Vec<u64>,
+    pub release_at: u64,
+}
+
+impl ReserveConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.reserve_bps > MAX_RESERVE_BPS {
+            return Err(format!("Reserve cannot exceed {} bps", MAX_RESERVE_BPS));
+        }
+        if self.reserve_bps > 0 && self.holdback_period == 0 {
+            return Err("A reserve needs a holdback period".to_string());
+        }
+        if self.holdback_period > MAX_HOLDBACK_PERIOD {
+            return Err("Holdback period cannot exceed one year".to_string());
+        }
+        Ok(())
+    }
+
+    /// Splits per-tranche credits into what is credited now and what is held back.
+    pub fn split(&self, amounts: &[u64]) -> (Vec<u64>, Vec<u64>) {
+        let bps = self.reserve_bps.min(BPS_SCALE);
+        amounts.iter()
+            .map(|&amount| {
+                let held = shares::apply_bps(amount, bps);
+                (amount - held, held)
+            })
+            .unzip()
+    }
+}
+
+impl ReserveHold {
+    pub fn total(&self) -> u64 {
+        self.held.iter().sum()
+    }
+
+    /// True once the holdback period has passed.
+    pub fn is_due(&self, now: u64) -> bool {
+        self.release_at <= now
+    }
+
+    /// Tops the hold up to the whole of `amounts`, the entry's credit per tranche, and
+    /// returns what that adds per tranche. Used to hold back a frozen entry.
+    pub fn hold_all(&mut self, amounts: &[u64]) -> Vec<u64> {
+        self.held.resize(amounts.len().max(self.held.len()), 0);
+        self.held.iter_mut()
+            .zip(amounts)
+            .map(|(held, &amount)| {
+                let added = amount.saturating_sub(*held);
+                *held += added;
+                added
+            })
+            .collect()
+    }
+
+    /// Takes up to `needed` per tranche out of the hold, lowering both, and returns the
+    /// total taken.
+    pub fn draw(&mut self, needed: &mut [u64]) -> u64 {
+        let mut drawn = 0;
+        for (held, needed) in self.held.iter_mut().zip(needed.iter_mut()) {
+            let amount = (*held).min(*needed);
+            *held -= amount;
+            *needed -= amount;
+            drawn += amount;
+        }
+        drawn
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(reserve_bps: u64) -> ReserveConfig {
+        ReserveConfig { reserve_bps, holdback_period: 1_000 }
+    }
+
+    #[test]
+    fn split_rounds_the_held_part_down() {
+        let (credited, held) = config(1_000).split(&[1_005, 9, 0]);
+        assert_eq!(held, vec![100, 0, 0]);
+        assert_eq!(credited, vec![905, 9, 0]);
+    }
+
+    #[test]
+    fn split_never_loses_revenue() {
+        for amount in [1, 3, 999, 1_001, u64::MAX / 2] {
+            let (credited, held) = config(3_333).split(&[amount]);
+            assert_eq!(credited[0] + held[0], amount);
+        }
+    }
+
+    #[test]
+    fn no_reserve_holds_nothing() {
+        let (credited, held) = ReserveConfig::default().split(&[500]);
+        assert_eq!((credited, held), (vec![500], vec![0]));
+    }
+
+    #[test]
+    fn validate_bounds() {
+        assert!(config(MAX_RESERVE_BPS).validate().is_ok());
+        assert!(config(MAX_RESERVE_BPS + 1).validate().is_err());
+        assert!(ReserveConfig { reserve_bps: 100, holdback_period: 0 }.validate().is_err());
+        assert!(ReserveConfig { reserve_bps: 0, holdback_period: MAX_HOLDBACK_PERIOD + 1 }.validate().is_err());
+    }
+
+    #[test]
+    fn hold_is_released_only_after_the_holdback() {
+        let hold = ReserveHold { revenue_index: 0, held: vec![100], release_at: 1_000 };
+        assert!(!hold.is_due(999));
+        assert!(hold.is_due(1_000));
+        assert!(hold.is_due(1_001));
+    }
+
+    #[test]
+    fn draw_larger_than_the_hold_takes_only_what_is_held() {
+        let mut hold = ReserveHold { revenue_index: 0, held: vec![100, 50], release_at: 0 };
+        let mut needed = vec![150, 20];
+
+        assert_eq!(hold.draw(&mut needed), 120);
+        assert_eq!(hold.held, vec![0, 30]);
+        assert_eq!(needed, vec![50, 0]);
+    }
+
+    #[test]
+    fn hold_all_tops_up_to_the_entry_credit() {
+        let mut hold = ReserveHold { revenue_index: 0, held: vec![100], release_at: 0 };
+        assert_eq!(hold.hold_all(&[400, 70]), vec![300, 70]);
+        assert_eq!(hold.held, vec![400, 70]);
+        assert_eq!(hold.hold_all(&[400, 70]), vec![0, 0]);
+    }
+}
@@ -0,0 +1,227 @@
+// Campaign-level rules on who may invest and how much.
+//
+// Tickets must fall between the minimum and maximum, except that a ticket which
+// exactly fills the remaining capacity may be below the minimum so the last slot can
+// always be taken. Denylisted principals can never invest; allowlisted ones are the
+// only ones admitted during the presale window, or at any time when the campaign is
+// allowlist-only.
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct InvestmentRules {
+    pub min_ticket: u64,
+    pub max_ticket: Option<u64>,
+    /// Most a single principal may invest across all their tickets
+    pub per_principal_cap: Option<u64>,
+    pub allowlist_only: bool,
+    /// Until this time only allowlisted principals may invest
+    pub presale_ends_at: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum AccessLevel {
+    Allowed,
+    Denied,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum InvestmentRejection {
+    VaultNotInitialized,
+    LedgerNotConfigured,
+    NotAcceptingInvestments,
+    DeadlinePassed,
+    TrancheNotFound,
+    TrancheFull,
+    BelowMinimum { minimum: u64 },
+    AboveMaximum { maximum: u64 },
+    ExceedsCapacity { available: u64 },
+    PrincipalCapReached { cap: u64, invested: u64 },
+    Denylisted,
+    NotAllowlisted,
+    PresaleOnly { ends_at: u64 },
+    TransferFailed { message: String },
+    /// The principal already has an investment, refund or claim in flight
+    CallInProgress,
+    Paused { reason: String },
+}
+
+impl InvestmentRejection {
+    pub fn message(&self) -> String {
+        match self {
+            InvestmentRejection::VaultNotInitialized => "Vault not initialized".to_string(),
+            InvestmentRejection::LedgerNotConfigured => "Ledger canister not configured".to_string(),
+            InvestmentRejection::NotAcceptingInvestments => "Campaign is no longer accepting investments".to_string(),
+            InvestmentRejection::DeadlinePassed => "Funding deadline has passed".to_string(),
+            InvestmentRejection::TrancheNotFound => "Tranche not found".to_string(),
+            InvestmentRejection::TrancheFull => "Tranche is fully subscribed".to_string(),
+            InvestmentRejection::BelowMinimum { minimum } => format!("Minimum investment is {}", minimum),
+            InvestmentRejection::AboveMaximum { maximum } => format!("Maximum investment is {}", maximum),
+            InvestmentRejection::ExceedsCapacity { available } => format!("Only {} is left to invest", available),
+            InvestmentRejection::PrincipalCapReached { cap, invested } => {
+                format!("Investment would exceed the per-backer cap of {} ({} already invested)", cap, invested)
+            }
+            InvestmentRejection::Denylisted => "Caller is not permitted to invest".to_string(),
+            InvestmentRejection::NotAllowlisted => "Campaign is open to allowlisted backers only".to_string(),
+            InvestmentRejection::PresaleOnly { ends_at } => {
+                format!("Presale is open to allowlisted backers only until {}", ends_at)
+            }
+            InvestmentRejection::TransferFailed { message } => message.clone(),
+            InvestmentRejection::Paused { reason } => format!("Investing is paused: {}", reason),
+            InvestmentRejection::CallInProgress => "Another call from this principal is still in progress".to_string(),
+        }
+    }
+}
+
+impl InvestmentRules {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(max_ticket) = self.max_ticket {
+            if max_ticket == 0 || max_ticket < self.min_ticket {
+                return Err("Maximum ticket must be at least the minimum ticket".to_string());
+            }
+        }
+
+        if self.per_principal_cap == Some(0) {
+            return Err("Per-backer cap must be greater than 0".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Checks a ticket of `amount` against the rules. `invested` is what the principal
+    /// has already invested, `available` the capacity left to fill.
+    pub fn check(
+        &self,
+        access: Option<AccessLevel>,
+        amount: u64,
+        invested: u64,
+        available: u64,
+        now: u64,
+    ) -> Result<(), InvestmentRejection> {
+        match access {
+            Some(AccessLevel::Denied) => return Err(InvestmentRejection::Denylisted),
+            Some(AccessLevel::Allowed) => {}
+            None if self.allowlist_only => return Err(InvestmentRejection::NotAllowlisted),
+            None => {
+                if let Some(ends_at) = self.presale_ends_at.filter(|ends_at| now < *ends_at) {
+                    return Err(InvestmentRejection::PresaleOnly { ends_at });
+                }
+            }
+        }
+
+        if amount > available {
+            return Err(InvestmentRejection::ExceedsCapacity { available });
+        }
+
+        let minimum = self.min_ticket.max(1);
+        if amount < minimum && amount != available {
+            return Err(InvestmentRejection::BelowMinimum { minimum });
+        }
+
+        if let Some(maximum) = self.max_ticket.filter(|maximum| amount > *maximum) {
+            return Err(InvestmentRejection::AboveMaximum { maximum });
+        }
+
+        if let Some(cap) = self.per_principal_cap {
+            if invested.saturating_add(amount) > cap {
+                return Err(InvestmentRejection::PrincipalCapReached { cap, invested });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules() -> InvestmentRules {
+        InvestmentRules {
+            min_ticket: 100,
+            max_ticket: Some(1_000),
+            per_principal_cap: Some(1_500),
+            allowlist_only: false,
+            presale_ends_at: None,
+        }
+    }
+
+    #[test]
+    fn ticket_bounds_are_inclusive() {
+        let rules = rules();
+        assert_eq!(rules.check(None, 100, 0, 10_000, 0), Ok(()));
+        assert_eq!(rules.check(None, 1_000, 0, 10_000, 0), Ok(()));
+        assert_eq!(rules.check(None, 99, 0, 10_000, 0), Err(InvestmentRejection::BelowMinimum { minimum: 100 }));
+        assert_eq!(rules.check(None, 1_001, 0, 10_000, 0), Err(InvestmentRejection::AboveMaximum { maximum: 1_000 }));
+    }
+
+    #[test]
+    fn zero_ticket_is_below_minimum_even_without_one() {
+        let rules = InvestmentRules::default();
+        assert_eq!(rules.check(None, 0, 0, 10_000, 0), Err(InvestmentRejection::BelowMinimum { minimum: 1 }));
+        assert_eq!(rules.check(None, 1, 0, 10_000, 0), Ok(()));
+    }
+
+    #[test]
+    fn last_slot_may_be_below_minimum() {
+        let rules = rules();
+        assert_eq!(rules.check(None, 40, 0, 40, 0), Ok(()));
+        assert_eq!(rules.check(None, 39, 0, 40, 0), Err(InvestmentRejection::BelowMinimum { minimum: 100 }));
+    }
+
+    #[test]
+    fn ticket_cannot_exceed_capacity() {
+        let rules = rules();
+        assert_eq!(rules.check(None, 500, 0, 500, 0), Ok(()));
+        assert_eq!(rules.check(None, 501, 0, 500, 0), Err(InvestmentRejection::ExceedsCapacity { available: 500 }));
+    }
+
+    #[test]
+    fn per_backer_cap_counts_earlier_tickets() {
+        let rules = rules();
+        assert_eq!(rules.check(None, 500, 1_000, 10_000, 0), Ok(()));
+        assert_eq!(
+            rules.check(None, 501, 1_000, 10_000, 0),
+            Err(InvestmentRejection::PrincipalCapReached { cap: 1_500, invested: 1_000 })
+        );
+    }
+
+    #[test]
+    fn denylisted_principal_is_always_rejected() {
+        let rules = rules();
+        assert_eq!(rules.check(Some(AccessLevel::Denied), 500, 0, 10_000, 0), Err(InvestmentRejection::Denylisted));
+    }
+
+    #[test]
+    fn allowlist_only_admits_allowlisted_principals() {
+        let rules = InvestmentRules { allowlist_only: true, ..rules() };
+        assert_eq!(rules.check(None, 500, 0, 10_000, 0), Err(InvestmentRejection::NotAllowlisted));
+        assert_eq!(rules.check(Some(AccessLevel::Allowed), 500, 0, 10_000, 0), Ok(()));
+    }
+
+    #[test]
+    fn presale_admits_only_allowlisted_until_it_ends() {
+        let rules = InvestmentRules { presale_ends_at: Some(1_000), ..rules() };
+        assert_eq!(rules.check(None, 500, 0, 10_000, 999), Err(InvestmentRejection::PresaleOnly { ends_at: 1_000 }));
+        assert_eq!(rules.check(Some(AccessLevel::Allowed), 500, 0, 10_000, 999), Ok(()));
+        assert_eq!(rules.check(None, 500, 0, 10_000, 1_000), Ok(()));
+    }
+
+    #[test]
+    fn allowlisting_does_not_lift_ticket_limits() {
+        let rules = rules();
+        assert_eq!(
+            rules.check(Some(AccessLevel::Allowed), 1_001, 0, 10_000, 0),
+            Err(InvestmentRejection::AboveMaximum { maximum: 1_000 })
+        );
+    }
+
+    #[test]
+    fn validate_rejects_inconsistent_limits() {
+        assert!(rules().validate().is_ok());
+        assert!(InvestmentRules { max_ticket: Some(99), ..rules() }.validate().is_err());
+        assert!(InvestmentRules { max_ticket: Some(0), min_ticket: 0, ..rules() }.validate().is_err());
+        assert!(InvestmentRules { per_principal_cap: Some(0), ..rules() }.validate().is_err());
+    }
+}
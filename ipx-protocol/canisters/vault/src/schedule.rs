@@ -0,0 +1,60 @@
+// Periodic jobs the vault runs on its own timers: paying accrued revenue out to
+// backers and asking the oracle to pull fresh revenue figures. Schedules and their
+// last outcome are persisted with the vault state; timers do not survive upgrades,
+// so `post_upgrade` re-arms every configured schedule.
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// Shortest interval a schedule may run at, in seconds
+pub const MIN_INTERVAL_SECONDS: u64 = 60 * 60;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ScheduledJob {
+    Distribution,
+    OraclePull,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Schedule {
+    pub interval_seconds: u64,
+    pub next_run: Option<u64>,
+    pub last_run: Option<u64>,
+    pub last_result: Option<String>,
+    pub last_error: Option<String>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ScheduleStatus {
+    pub distribution: Option<Schedule>,
+    pub oracle_pull: Option<Schedule>,
+}
+
+impl Schedule {
+    pub fn new(interval_seconds: u64) -> Self {
+        Schedule {
+            interval_seconds,
+            next_run: None,
+            last_run: None,
+            last_result: None,
+            last_error: None,
+        }
+    }
+
+    pub fn interval_nanos(&self) -> u64 {
+        self.interval_seconds.saturating_mul(1_000_000_000)
+    }
+
+    /// Records the outcome of a run that started at `now`.
+    pub fn record(&mut self, now: u64, result: Result<String, String>) {
+        self.last_run = Some(now);
+        self.next_run = Some(now.saturating_add(self.interval_nanos()));
+        match result {
+            Ok(summary) => {
+                self.last_result = Some(summary);
+                self.last_error = None;
+            }
+            Err(e) => self.last_error = Some(e),
+        }
+    }
+}
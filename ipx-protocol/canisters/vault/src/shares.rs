@@ -0,0 +1,181 @@
+// Integer share accounting. Ownership shares are fixed-point fractions scaled by
+// SHARE_SCALE (1e18 == 100%); revenue splits use whole percentages or basis points.
+
+pub const SHARE_SCALE: u128 = 1_000_000_000_000_000_000;
+pub const BPS_SCALE: u64 = 10_000;
+
+/// `amount / total` as a fixed-point fraction, rounded down.
+pub fn share_of(amount: u64, total: u64) -> u128 {
+    if total == 0 {
+        return 0;
+    }
+    amount as u128 * SHARE_SCALE / total as u128
+}
+
+/// `amount * bps / BPS_SCALE`, rounded down.
+pub fn apply_bps(amount: u64, bps: u64) -> u64 {
+    (amount as u128 * bps as u128 / BPS_SCALE as u128) as u64
+}
+
+/// `share` expressed in basis points, rounded down.
+pub fn share_to_bps(share: u128) -> u64 {
+    (share * BPS_SCALE as u128 / SHARE_SCALE) as u64
+}
+
+/// Adds `amount` to a cumulative per-unit accumulator spread over `total_units`.
+/// The division remainder is carried into the next call, so no revenue is lost to
+/// rounding at the accumulator level. Returns the new `(per_unit, carry)` pair.
+pub fn accrue(per_unit: u128, carry: u128, amount: u64, total_units: u64) -> (u128, u128) {
+    if total_units == 0 {
+        return (per_unit, carry);
+    }
+    let numerator = amount as u128 * SHARE_SCALE + carry;
+    (
+        per_unit + numerator / total_units as u128,
+        numerator % total_units as u128,
+    )
+}
+
+/// Takes `amount` previously added with `accrue` back out of the accumulator.
+/// `per_unit * total_units + carry` is exactly the scaled sum of everything accrued,
+/// so the reversal is exact as long as `total_units` has not changed since.
+pub fn reverse(per_unit: u128, carry: u128, amount: u64, total_units: u64) -> (u128, u128) {
+    if total_units == 0 {
+        return (per_unit, carry);
+    }
+    let accrued = (per_unit * total_units as u128 + carry).saturating_sub(amount as u128 * SHARE_SCALE);
+    (accrued / total_units as u128, accrued % total_units as u128)
+}
+
+/// Lifetime amount owed to a holder of `units` at accumulator value `per_unit`.
+/// Rounds down, so holders together are never owed more than was accrued.
+pub fn accrued(units: u64, per_unit: u128) -> u64 {
+    ((units as u128).saturating_mul(per_unit) / SHARE_SCALE) as u64
+}
+
+/// Rounding dust left in an accumulator: the part of everything accrued over
+/// `total_units` that `accrued` assigns to none of the holders of `units`, which must
+/// add up to `total_units`. Always less than one per holder plus one.
+pub fn dust(per_unit: u128, carry: u128, units: impl IntoIterator<Item = u64>, total_units: u64) -> u64 {
+    let total = ((per_unit * total_units as u128 + carry) / SHARE_SCALE) as u64;
+    let assigned: u64 = units.into_iter().map(|units| accrued(units, per_unit)).sum();
+    total.saturating_sub(assigned)
+}
+
+/// Splits `total` across `weights` pro rata using the largest-remainder method.
+///
+/// Every recipient first receives the floor of its exact entitlement; the leftover
+/// units ("dust", always fewer than the number of recipients) go one each to the
+/// recipients with the largest fractional remainders, ties broken by input order.
+/// The returned amounts therefore always sum to exactly `total` whenever any
+/// weight is non-zero, and the result depends only on the inputs.
+pub fn allocate<K: Clone>(total: u64, weights: &[(K, u64)]) -> Vec<(K, u64)> {
+    let total_weight: u128 = weights.iter().map(|(_, w)| *w as u128).sum();
+    if total_weight == 0 {
+        return weights.iter().map(|(k, _)| (k.clone(), 0)).collect();
+    }
+
+    let mut allocations = Vec::with_capacity(weights.len());
+    let mut remainders = Vec::with_capacity(weights.len());
+    let mut allocated: u128 = 0;
+
+    for (index, (key, weight)) in weights.iter().enumerate() {
+        let exact = total as u128 * *weight as u128;
+        let base = exact / total_weight;
+        allocated += base;
+        allocations.push((key.clone(), base as u64));
+        remainders.push((exact % total_weight, index));
+    }
+
+    // Largest remainder first; equal remainders keep input order
+    remainders.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+    let dust = (total as u128 - allocated) as usize;
+    for &(_, index) in remainders.iter().take(dust) {
+        allocations[index].1 += 1;
+    }
+
+    debug_assert_eq!(
+        allocations.iter().map(|(_, amount)| *amount as u128).sum::<u128>(),
+        total as u128
+    );
+    allocations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn allocate_preserves_total(total in any::<u64>(), weights in prop::collection::vec(0u64..=u64::MAX, 1..40)) {
+            let weighted: Vec<(usize, u64)> = weights.iter().copied().enumerate().collect();
+            let allocations = allocate(total, &weighted);
+            let sum: u128 = allocations.iter().map(|(_, amount)| *amount as u128).sum();
+
+            if weights.iter().any(|w| *w > 0) {
+                prop_assert_eq!(sum, total as u128);
+            } else {
+                prop_assert_eq!(sum, 0);
+            }
+        }
+
+        #[test]
+        fn allocate_stays_within_one_of_exact_share(total in any::<u64>(), weights in prop::collection::vec(1u64..=u64::MAX, 1..40)) {
+            let weighted: Vec<(usize, u64)> = weights.iter().copied().enumerate().collect();
+            let total_weight: u128 = weights.iter().map(|w| *w as u128).sum();
+
+            for (index, amount) in allocate(total, &weighted) {
+                let floor = total as u128 * weights[index] as u128 / total_weight;
+                prop_assert!(amount as u128 >= floor);
+                prop_assert!(amount as u128 <= floor + 1);
+            }
+        }
+
+        #[test]
+        fn reverse_undoes_accrue(
+            earlier in prop::collection::vec(0u64..1_000_000_000_000, 0..20),
+            amount in 0u64..1_000_000_000_000,
+            total_units in 1u64..=u64::MAX,
+        ) {
+            let (per_unit, carry) = earlier.iter()
+                .fold((0, 0), |(per_unit, carry), a| accrue(per_unit, carry, *a, total_units));
+            let (accrued_per_unit, accrued_carry) = accrue(per_unit, carry, amount, total_units);
+
+            prop_assert_eq!(reverse(accrued_per_unit, accrued_carry, amount, total_units), (per_unit, carry));
+        }
+
+        #[test]
+        fn accrued_plus_dust_balances(
+            units in prop::collection::vec(1u64..1_000_000_000_000, 1..50),
+            amounts in prop::collection::vec(0u64..1_000_000_000_000, 0..20),
+        ) {
+            let total_units: u64 = units.iter().sum();
+            let (per_unit, carry) = amounts.iter()
+                .fold((0, 0), |(per_unit, carry), a| accrue(per_unit, carry, *a, total_units));
+
+            let assigned: u64 = units.iter().map(|units| accrued(*units, per_unit)).sum();
+            let dust = dust(per_unit, carry, units.iter().copied(), total_units);
+
+            prop_assert_eq!(assigned + dust, amounts.iter().sum::<u64>());
+            prop_assert!(dust <= units.len() as u64);
+        }
+
+        #[test]
+        fn reversal_order_does_not_matter(
+            amounts in prop::collection::vec(0u64..1_000_000_000_000, 1..20),
+            reversed in any::<prop::sample::Index>(),
+            total_units in 1u64..=u64::MAX,
+        ) {
+            let reversed = reversed.index(amounts.len());
+            let (per_unit, carry) = amounts.iter()
+                .fold((0, 0), |(per_unit, carry), a| accrue(per_unit, carry, *a, total_units));
+            let without = amounts.iter().enumerate()
+                .filter(|(index, _)| *index != reversed)
+                .fold((0, 0), |(per_unit, carry), (_, a)| accrue(per_unit, carry, *a, total_units));
+
+            prop_assert_eq!(reverse(per_unit, carry, amounts[reversed], total_units), without);
+        }
+    }
+}
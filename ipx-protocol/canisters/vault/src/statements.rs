@@ -0,0 +1,373 @@
+// Per-period snapshots and backer statements.
+//
+// The vault keeps one snapshot per calendar month with the revenue, fees, backer
+// credits and payouts recorded in it, and each tranche's revenue-per-unit
+// accumulator at the start and end of the month. A lot's earnings for a month are
+// the growth of its tranche's accumulator over the month. When its position NFT
+// changes hands the vault records the new owner with the accumulator at that moment,
+// so the month's earnings are split between owners at that point and each backer's
+// statement shows what they earned while they held the lot. Corrections count in the
+// month they were made, which can make a month's figures negative.
+
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+use crate::history::month_of;
+use crate::shares;
+use crate::{InvestmentLot, RevenueUpdate};
+
+/// Longest range a single statement may cover, in months
+pub const MAX_STATEMENT_MONTHS: u32 = 120;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PeriodSnapshot {
+    /// Calendar month in UTC as `YYYYMM`
+    pub month: u32,
+    pub revenue: i128,
+    pub protocol_fees: i128,
+    pub creator_fees: i128,
+    /// Credited to backers through the waterfall
+    pub distributable: i128,
+    /// Streamed to payees, net of payout fees
+    pub paid_out: u64,
+    pub payout_fees: u64,
+    /// Each tranche's revenue-per-unit accumulator when the month opened and closed
+    pub opening_rates: Vec<u128>,
+    pub closing_rates: Vec<u128>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Granularity {
+    Monthly,
+    Quarterly,
+}
+
+/// Snapshot figures summed over a month or a quarter.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PeriodSummary {
+    /// `2025-03` for a month, `2025-Q1` for a quarter
+    pub label: String,
+    pub revenue: i128,
+    pub protocol_fees: i128,
+    pub creator_fees: i128,
+    pub distributable: i128,
+    pub paid_out: u64,
+    pub payout_fees: u64,
+}
+
+/// In the order lines of the same month are listed.
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LineKind {
+    Investment,
+    TransferIn,
+    TransferOut,
+    Accrual,
+}
+
+/// Someone who held a position NFT from `since` until the next record. Before its
+/// first record a lot belongs to the backer who invested it.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct OwnerRecord {
+    pub owner: Principal,
+    pub since: u64,
+    /// The lot's tranche revenue-per-unit accumulator at `since`
+    pub rate: u128,
+}
+
+/// A lot a statement covers, with its owners of record after the investor, oldest first.
+pub struct StatementLot {
+    pub investor: Principal,
+    pub lot_index: u32,
+    pub lot: InvestmentLot,
+    pub owners: Vec<OwnerRecord>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct StatementLine {
+    pub month: u32,
+    pub kind: LineKind,
+    pub lot_index: u32,
+    pub tranche: u32,
+    /// Invested or transferred principal in ledger units, or revenue earned in payout
+    /// token units
+    pub amount: i128,
+    /// Ledger block of an investment, position NFT of a transfer or an accruing lot
+    pub reference: String,
+}
+
+/// A backer's investments, the positions they took over or handed on, and the revenue
+/// each lot earned while they held it.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct BackerStatement {
+    pub backer: Principal,
+    pub from_month: u32,
+    pub to_month: u32,
+    pub lines: Vec<StatementLine>,
+    pub total_invested: u64,
+    pub total_earned: i128,
+    /// The lines as CSV with a header row
+    pub csv: String,
+}
+
+impl PeriodSnapshot {
+    pub fn new(month: u32, opening_rates: Vec<u128>) -> Self {
+        PeriodSnapshot {
+            month,
+            closing_rates: opening_rates.clone(),
+            opening_rates,
+            ..PeriodSnapshot::default()
+        }
+    }
+
+    /// Counts a revenue entry (`sign` 1) or takes it back out (`sign` -1).
+    pub fn add_revenue(&mut self, entry: &RevenueUpdate, sign: i128) {
+        self.revenue += sign * entry.amount as i128;
+        self.protocol_fees += sign * entry.protocol_fee as i128;
+        self.creator_fees += sign * entry.creator_fee as i128;
+        self.distributable += sign * entry.tranche_amounts.iter().map(|a| *a as i128).sum::<i128>();
+    }
+}
+
+impl StatementLot {
+    fn reference(&self) -> String {
+        self.lot.nft_token_id.map(|token_id| format!("token {}", token_id)).unwrap_or_default()
+    }
+
+    /// What each owner of record earned on the lot over the month of `snapshot`.
+    /// The amounts add up to the lot's earnings for the whole month.
+    pub fn earnings(&self, snapshot: &PeriodSnapshot) -> Vec<(Principal, i128)> {
+        let tranche_rate = |rates: &[u128]| rates.get(self.lot.tranche as usize).copied().unwrap_or(0);
+        let accrued = |rate: u128| shares::accrued(self.lot.amount, rate) as i128;
+
+        let mut owner = self.owners.iter()
+            .take_while(|record| month_of(record.since) < snapshot.month)
+            .last()
+            .map_or(self.investor, |record| record.owner);
+        let mut rate = tranche_rate(&snapshot.opening_rates);
+
+        let mut earnings = Vec::new();
+        for record in self.owners.iter().filter(|record| month_of(record.since) == snapshot.month) {
+            earnings.push((owner, accrued(record.rate) - accrued(rate)));
+            owner = record.owner;
+            rate = record.rate;
+        }
+        earnings.push((owner, accrued(tranche_rate(&snapshot.closing_rates)) - accrued(rate)));
+        earnings
+    }
+}
+
+/// Checks a `YYYYMM` range for a statement.
+pub fn validate_range(from_month: u32, to_month: u32) -> Result<(), String> {
+    for month in [from_month, to_month] {
+        if !(1..=12).contains(&(month % 100)) {
+            return Err(format!("{} is not a month in YYYYMM form", month));
+        }
+    }
+    let months = |month: u32| (month / 100) * 12 + month % 100;
+    if from_month > to_month {
+        return Err("Statement range starts after it ends".to_string());
+    }
+    if months(to_month) - months(from_month) >= MAX_STATEMENT_MONTHS {
+        return Err(format!("Statements cover at most {} months", MAX_STATEMENT_MONTHS));
+    }
+    Ok(())
+}
+
+/// `2025-03` for months, `2025-Q1` for quarters.
+pub fn period_label(month: u32, granularity: Granularity) -> String {
+    let (year, month) = (month / 100, month % 100);
+    match granularity {
+        Granularity::Monthly => format!("{}-{:02}", year, month),
+        Granularity::Quarterly => format!("{}-Q{}", year, month.div_ceil(3)),
+    }
+}
+
+/// Sums `snapshots`, in month order, into one summary per period.
+pub fn summarize(snapshots: Vec<PeriodSnapshot>, granularity: Granularity) -> Vec<PeriodSummary> {
+    let mut summaries: Vec<PeriodSummary> = Vec::new();
+    for snapshot in snapshots {
+        let label = period_label(snapshot.month, granularity);
+        if summaries.last().is_none_or(|summary| summary.label != label) {
+            summaries.push(PeriodSummary { label, ..PeriodSummary::default() });
+        }
+        if let Some(summary) = summaries.last_mut() {
+            summary.revenue += snapshot.revenue;
+            summary.protocol_fees += snapshot.protocol_fees;
+            summary.creator_fees += snapshot.creator_fees;
+            summary.distributable += snapshot.distributable;
+            summary.paid_out += snapshot.paid_out;
+            summary.payout_fees += snapshot.payout_fees;
+        }
+    }
+    summaries
+}
+
+/// Builds the statement for `backer` over the months covered by `snapshots`, from the
+/// lots they invested or held.
+pub fn backer_statement(
+    backer: Principal,
+    lots: &[StatementLot],
+    from_month: u32,
+    to_month: u32,
+    snapshots: &[PeriodSnapshot],
+    payout_unit: &str,
+) -> BackerStatement {
+    let mut lines = Vec::new();
+    let mut push = |month: u32, kind: LineKind, lot: &StatementLot, amount: i128, reference: String| {
+        if (from_month..=to_month).contains(&month) {
+            lines.push(StatementLine {
+                month,
+                kind,
+                lot_index: lot.lot_index,
+                tranche: lot.lot.tranche,
+                amount,
+                reference,
+            });
+        }
+    };
+
+    for lot in lots {
+        if lot.investor == backer {
+            let reference = format!("block {}", lot.lot.ledger_block_index);
+            push(month_of(lot.lot.timestamp), LineKind::Investment, lot, lot.lot.amount as i128, reference);
+        }
+
+        let mut previous = lot.investor;
+        for record in &lot.owners {
+            if record.owner == previous {
+                continue;
+            }
+            let month = month_of(record.since);
+            if record.owner == backer {
+                let reference = format!("{} from {}", lot.reference(), previous.to_text());
+                push(month, LineKind::TransferIn, lot, lot.lot.amount as i128, reference);
+            } else if previous == backer {
+                let reference = format!("{} to {}", lot.reference(), record.owner.to_text());
+                push(month, LineKind::TransferOut, lot, lot.lot.amount as i128, reference);
+            }
+            previous = record.owner;
+        }
+
+        for snapshot in snapshots {
+            let earned: i128 = lot.earnings(snapshot).into_iter()
+                .filter(|(owner, _)| *owner == backer)
+                .map(|(_, earned)| earned)
+                .sum();
+            if earned != 0 {
+                push(snapshot.month, LineKind::Accrual, lot, earned, lot.reference());
+            }
+        }
+    }
+
+    lines.sort_by_key(|line| (line.month, line.kind, line.lot_index));
+
+    let total_invested = lines.iter()
+        .filter(|line| line.kind == LineKind::Investment)
+        .map(|line| line.amount as u64)
+        .sum();
+    let total_earned = lines.iter()
+        .filter(|line| line.kind == LineKind::Accrual)
+        .map(|line| line.amount)
+        .sum();
+    let csv = to_csv(&lines, payout_unit);
+
+    BackerStatement {
+        backer,
+        from_month,
+        to_month,
+        lines,
+        total_invested,
+        total_earned,
+        csv,
+    }
+}
+
+fn to_csv(lines: &[StatementLine], payout_unit: &str) -> String {
+    let mut csv = String::from("period,type,lot,tranche,amount,unit,reference\n");
+    for line in lines {
+        let (kind, unit) = match line.kind {
+            LineKind::Investment => ("investment", "ledger"),
+            LineKind::TransferIn => ("transfer in", "ledger"),
+            LineKind::TransferOut => ("transfer out", "ledger"),
+            LineKind::Accrual => ("revenue", payout_unit),
+        };
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            period_label(line.month, Granularity::Monthly),
+            kind,
+            line.lot_index,
+            line.tranche,
+            line.amount,
+            unit,
+            line.reference
+        ));
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: u64 = 1_000_000_000;
+    const JAN_5_2024: u64 = 1_704_412_800 * SECOND;
+    const FEB_15_2024: u64 = 1_707_955_200 * SECOND;
+
+    fn lot_sold_in_february(investor: Principal, buyer: Principal) -> StatementLot {
+        StatementLot {
+            investor,
+            lot_index: 0,
+            lot: InvestmentLot {
+                amount: 1_000,
+                share: 0,
+                timestamp: JAN_5_2024,
+                ledger_block_index: 7,
+                nft_token_id: Some(3),
+                tranche: 0,
+                claimed: 0,
+            },
+            owners: vec![OwnerRecord { owner: buyer, since: FEB_15_2024, rate: 2 * shares::SHARE_SCALE / 10 }],
+        }
+    }
+
+    fn snapshots() -> Vec<PeriodSnapshot> {
+        let rate = |tenths: u128| vec![tenths * shares::SHARE_SCALE / 10];
+        vec![
+            PeriodSnapshot { closing_rates: rate(1), ..PeriodSnapshot::new(202401, rate(0)) },
+            PeriodSnapshot { closing_rates: rate(5), ..PeriodSnapshot::new(202402, rate(1)) },
+        ]
+    }
+
+    #[test]
+    fn earnings_split_at_transfer() {
+        let (investor, buyer) = (Principal::from_slice(&[1]), Principal::from_slice(&[2]));
+        let lot = lot_sold_in_february(investor, buyer);
+        let snapshots = snapshots();
+
+        assert_eq!(lot.earnings(&snapshots[0]), vec![(investor, 100)]);
+        assert_eq!(lot.earnings(&snapshots[1]), vec![(investor, 100), (buyer, 300)]);
+    }
+
+    #[test]
+    fn statements_follow_the_owner_of_record() {
+        let (investor, buyer) = (Principal::from_slice(&[1]), Principal::from_slice(&[2]));
+        let lots = [lot_sold_in_february(investor, buyer)];
+        let snapshots = snapshots();
+
+        let sold = backer_statement(investor, &lots, 202401, 202402, &snapshots, "USDC");
+        let kinds: Vec<(u32, LineKind, i128)> = sold.lines.iter().map(|line| (line.month, line.kind, line.amount)).collect();
+        assert_eq!(kinds, vec![
+            (202401, LineKind::Investment, 1_000),
+            (202401, LineKind::Accrual, 100),
+            (202402, LineKind::TransferOut, 1_000),
+            (202402, LineKind::Accrual, 100),
+        ]);
+        assert_eq!((sold.total_invested, sold.total_earned), (1_000, 200));
+
+        let bought = backer_statement(buyer, &lots, 202401, 202402, &snapshots, "USDC");
+        let kinds: Vec<(u32, LineKind, i128)> = bought.lines.iter().map(|line| (line.month, line.kind, line.amount)).collect();
+        assert_eq!(kinds, vec![(202402, LineKind::TransferIn, 1_000), (202402, LineKind::Accrual, 300)]);
+        assert_eq!((bought.total_invested, bought.total_earned), (0, 300));
+        assert!(bought.csv.contains("2024-02,transfer in,0,0,1000,ledger,token 3 from"));
+    }
+}
@@ -0,0 +1,98 @@
+// Stable-memory layout and encoding for vault state.
+//
+// Records are CBOR-encoded so that fields added later with `#[serde(default)]`
+// still decode from memory written by an older vault wasm. Incompatible layout
+// changes must bump `SCHEMA_VERSION` and add a migration in `post_upgrade`.
+
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{storable::Bound, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+use crate::history::{MonthKey, MonthlyRevenue};
+use crate::ingestion::{RecordedEvent, RevenueEventKey, SnapshotKey};
+use crate::journal::JournalEntry;
+use crate::outbox::OutboxItem;
+use crate::pause::PauseEvent;
+use crate::rates::RevenueAmount;
+use crate::reserve::ReserveHold;
+use crate::rules::AccessLevel;
+use crate::statements::{OwnerRecord, PeriodSnapshot};
+use crate::{BackerInfo, PendingMint, RevenueDispute, RevenueUpdate, VaultState};
+
+pub const SCHEMA_VERSION: u32 = 11;
+
+pub const CONFIG_MEMORY_ID: MemoryId = MemoryId::new(0);
+pub const BACKERS_MEMORY_ID: MemoryId = MemoryId::new(1);
+pub const REVENUE_INDEX_MEMORY_ID: MemoryId = MemoryId::new(2);
+pub const REVENUE_DATA_MEMORY_ID: MemoryId = MemoryId::new(3);
+pub const DISPUTES_MEMORY_ID: MemoryId = MemoryId::new(4);
+/// Retired in schema 7; queued mints moved to the outbox
+pub const PENDING_MINTS_MEMORY_ID: MemoryId = MemoryId::new(5);
+pub const POSITIONS_MEMORY_ID: MemoryId = MemoryId::new(6);
+pub const MILESTONE_VOTES_MEMORY_ID: MemoryId = MemoryId::new(7);
+pub const ACCESS_LIST_MEMORY_ID: MemoryId = MemoryId::new(8);
+pub const JOURNAL_INDEX_MEMORY_ID: MemoryId = MemoryId::new(9);
+pub const JOURNAL_DATA_MEMORY_ID: MemoryId = MemoryId::new(10);
+pub const OUTBOX_MEMORY_ID: MemoryId = MemoryId::new(11);
+pub const BACKERS_BY_AMOUNT_MEMORY_ID: MemoryId = MemoryId::new(12);
+pub const MONTHLY_REVENUE_MEMORY_ID: MemoryId = MemoryId::new(13);
+pub const REVENUE_EVENTS_MEMORY_ID: MemoryId = MemoryId::new(14);
+pub const REVENUE_SNAPSHOTS_MEMORY_ID: MemoryId = MemoryId::new(15);
+pub const PAUSE_HISTORY_INDEX_MEMORY_ID: MemoryId = MemoryId::new(16);
+pub const PAUSE_HISTORY_DATA_MEMORY_ID: MemoryId = MemoryId::new(17);
+pub const PERIOD_SNAPSHOTS_MEMORY_ID: MemoryId = MemoryId::new(18);
+pub const RESERVE_HOLDS_MEMORY_ID: MemoryId = MemoryId::new(19);
+pub const OUTBOX_KEYS_MEMORY_ID: MemoryId = MemoryId::new(20);
+pub const OUTBOX_PENDING_MEMORY_ID: MemoryId = MemoryId::new(21);
+pub const OUTBOX_COMPLETED_MEMORY_ID: MemoryId = MemoryId::new(22);
+pub const POSITION_OWNERS_MEMORY_ID: MemoryId = MemoryId::new(23);
+
+/// Vault configuration and counters, written in `pre_upgrade` and restored in
+/// `post_upgrade`. Backers and revenue history live in their own regions.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PersistedState {
+    pub schema_version: u32,
+    pub state: Option<VaultState>,
+}
+
+/// A backer record as the first stable-memory vault wrote it, with a single position
+/// instead of lots. Only read by the schema 10 migration; `lots` tells records that
+/// were already written with lots apart.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LegacyBackerInfo {
+    pub amount_invested: u64,
+    pub investment_timestamp: u64,
+    #[serde(default)]
+    pub nft_token_id: Option<u64>,
+    #[serde(default)]
+    pub ledger_block_index: Option<u64>,
+    #[serde(default)]
+    pub lots: Vec<ciborium::Value>,
+}
+
+macro_rules! impl_cbor_storable {
+    ($($t:ty),* $(,)?) => {
+        $(
+            impl Storable for $t {
+                fn to_bytes(&self) -> Cow<'_, [u8]> {
+                    let mut bytes = Vec::new();
+                    ciborium::ser::into_writer(self, &mut bytes)
+                        .expect(concat!("Failed to encode ", stringify!($t)));
+                    Cow::Owned(bytes)
+                }
+
+                fn from_bytes(bytes: Cow<[u8]>) -> Self {
+                    ciborium::de::from_reader(bytes.as_ref())
+                        .expect(concat!("Failed to decode ", stringify!($t)))
+                }
+
+                const BOUND: Bound = Bound::Unbounded;
+            }
+        )*
+    };
+}
+
+impl_cbor_storable!(PersistedState, BackerInfo, LegacyBackerInfo, RevenueUpdate, RevenueDispute, PendingMint, AccessLevel, JournalEntry, OutboxItem, MonthKey, MonthlyRevenue,
+    RevenueEventKey, SnapshotKey, RecordedEvent, RevenueAmount, PauseEvent, PeriodSnapshot, ReserveHold, OwnerRecord,
+);
@@ -0,0 +1,279 @@
+// Revenue waterfall: how the backers' pool of each revenue update is split
+// between tranches.
+//
+// Tranches are paid in priority order (lower number first). Within a priority
+// level, each tranche is first brought up to its preferred return (principal
+// plus hurdle); levels with equal priority are paid pari passu. Whatever is left
+// is shared by all tranches in proportion to the principal they raised, never
+// pushing a tranche past its cap. Anything no tranche can absorb is retained by
+// the creator.
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+use crate::shares::{self, BPS_SCALE};
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct WaterfallConfig {
+    pub tranches: Vec<TrancheConfig>,
+    pub bonus_tiers: Vec<BonusTier>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TrancheConfig {
+    pub name: String,
+    pub priority: u8,
+    /// Principal this tranche raises; tranche sizes add up to the funding goal
+    pub size: u64,
+    /// Return on principal paid before lower-priority tranches, in basis points
+    pub hurdle_bps: u64,
+    /// Maximum lifetime payout as a multiple of principal, in basis points
+    pub cap_bps: Option<u64>,
+}
+
+/// Raises the backers' share of revenue above `revenue_threshold` (cumulative).
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct BonusTier {
+    pub revenue_threshold: u64,
+    pub additional_share_percentage: u8,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TrancheState {
+    pub raised: u64,
+    /// Lifetime amount credited to this tranche by the waterfall
+    pub received: u64,
+    /// Cumulative revenue per invested unit, scaled by `shares::SHARE_SCALE`
+    pub revenue_per_unit: u128,
+    pub revenue_per_unit_carry: u128,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct WaterfallPreview {
+    pub backer_pool: u64,
+    pub tranche_amounts: Vec<(String, u64)>,
+    pub retained: u64,
+}
+
+impl WaterfallConfig {
+    /// A single tranche covering the whole goal: the flat revenue share.
+    pub fn single_tranche(funding_goal: u64) -> Self {
+        WaterfallConfig {
+            tranches: vec![TrancheConfig {
+                name: "backers".to_string(),
+                priority: 0,
+                size: funding_goal,
+                hurdle_bps: 0,
+                cap_bps: None,
+            }],
+            bonus_tiers: Vec::new(),
+        }
+    }
+
+    pub fn validate(&self, funding_goal: u64, base_percentage: u8) -> Result<(), String> {
+        if self.tranches.is_empty() {
+            return Err("Waterfall needs at least one tranche".to_string());
+        }
+
+        let total_size: u128 = self.tranches.iter().map(|t| t.size as u128).sum();
+        if total_size != funding_goal as u128 {
+            return Err("Tranche sizes must add up to the funding goal".to_string());
+        }
+
+        if self.tranches.iter().any(|t| t.size == 0) {
+            return Err("Tranche size must be greater than 0".to_string());
+        }
+
+        let total_percentage: u32 = base_percentage as u32
+            + self.bonus_tiers.iter().map(|b| b.additional_share_percentage as u32).sum::<u32>();
+        if total_percentage > 100 {
+            return Err("Revenue share plus bonus tiers cannot exceed 100%".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Backers' lifetime pool for `cumulative_revenue`, bonus tiers included.
+    /// Computed on cumulative revenue so rounding never drifts across updates, and
+    /// rounded down once over the base share and all tiers together, so the pool
+    /// never grows faster than revenue.
+    pub fn backer_pool(&self, base_percentage: u8, cumulative_revenue: u64) -> u64 {
+        let bonus: u128 = self
+            .bonus_tiers
+            .iter()
+            .map(|tier| {
+                cumulative_revenue.saturating_sub(tier.revenue_threshold) as u128
+                    * tier.additional_share_percentage as u128
+            })
+            .sum();
+
+        let pool = (cumulative_revenue as u128 * base_percentage as u128 + bonus) / 100;
+        pool.min(cumulative_revenue as u128) as u64
+    }
+
+    /// Splits `pool` across tranches. Returns per-tranche amounts (in config
+    /// order) and the amount no tranche could absorb.
+    pub fn distribute(&self, tranches: &[TrancheState], pool: u64) -> (Vec<u64>, u64) {
+        let count = self.tranches.len();
+        let mut amounts = vec![0u64; count];
+        let mut remaining = pool;
+
+        let cap = |i: usize| -> u64 {
+            let raised = tranches[i].raised;
+            match self.tranches[i].cap_bps {
+                Some(cap_bps) => (raised as u128 * cap_bps as u128 / BPS_SCALE as u128).min(u64::MAX as u128) as u64,
+                None => u64::MAX,
+            }
+        };
+        let headroom = |i: usize, amounts: &[u64]| -> u64 {
+            cap(i).saturating_sub(tranches[i].received.saturating_add(amounts[i]))
+        };
+
+        // Preferred returns, one priority level at a time
+        let mut priorities: Vec<u8> = self.tranches.iter().map(|t| t.priority).collect();
+        priorities.sort_unstable();
+        priorities.dedup();
+
+        for priority in priorities {
+            if remaining == 0 {
+                break;
+            }
+
+            let needs: Vec<(usize, u64)> = (0..count)
+                .filter(|&i| self.tranches[i].priority == priority)
+                .map(|i| {
+                    let raised = tranches[i].raised;
+                    let preferred = raised.saturating_add(
+                        (raised as u128 * self.tranches[i].hurdle_bps as u128 / BPS_SCALE as u128) as u64,
+                    );
+                    let need = preferred
+                        .saturating_sub(tranches[i].received)
+                        .min(headroom(i, &amounts));
+                    (i, need)
+                })
+                .collect();
+
+            let total_need: u64 = needs.iter().map(|(_, need)| *need).sum();
+            if remaining >= total_need {
+                for (i, need) in needs {
+                    amounts[i] += need;
+                }
+                remaining -= total_need;
+            } else {
+                for (i, amount) in shares::allocate(remaining, &needs) {
+                    amounts[i] += amount;
+                }
+                remaining = 0;
+            }
+        }
+
+        // Residual, pro rata to principal among tranches still under their cap.
+        // Each pass either places everything or caps at least one more tranche.
+        while remaining > 0 {
+            let eligible: Vec<(usize, u64)> = (0..count)
+                .filter(|&i| tranches[i].raised > 0 && headroom(i, &amounts) > 0)
+                .map(|i| (i, tranches[i].raised))
+                .collect();
+
+            if eligible.is_empty() {
+                break;
+            }
+
+            let mut leftover = 0;
+            for (i, amount) in shares::allocate(remaining, &eligible) {
+                let placed = amount.min(headroom(i, &amounts));
+                amounts[i] += placed;
+                leftover += amount - placed;
+            }
+            remaining = leftover;
+        }
+
+        (amounts, remaining)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const MAX_AMOUNT: u64 = 1_000_000_000_000_000;
+
+    fn tranche() -> impl Strategy<Value = (TrancheConfig, TrancheState)> {
+        (0u8..4, 0u64..=MAX_AMOUNT, 0u64..=MAX_AMOUNT, 0u64..50_000, prop::option::of(0u64..50_000))
+            .prop_map(|(priority, raised, received, hurdle_bps, cap_bps)| {
+                let config = TrancheConfig { name: String::new(), priority, size: raised.max(1), hurdle_bps, cap_bps };
+                let state = TrancheState { raised, received, ..TrancheState::default() };
+                (config, state)
+            })
+    }
+
+    /// At most four tiers of up to 20% and a base of up to 20% stay within 100%
+    fn bonus_tier() -> impl Strategy<Value = BonusTier> {
+        (0u64..=MAX_AMOUNT, 0u8..=20).prop_map(|(revenue_threshold, additional_share_percentage)| BonusTier {
+            revenue_threshold,
+            additional_share_percentage,
+        })
+    }
+
+    #[test]
+    fn backer_pool_rounds_once_over_all_rates() {
+        let config = WaterfallConfig {
+            tranches: Vec::new(),
+            bonus_tiers: vec![BonusTier { revenue_threshold: 0, additional_share_percentage: 50 }],
+        };
+
+        assert_eq!(config.backer_pool(50, 1), 1);
+        assert_eq!(config.backer_pool(50, 2), 2);
+        assert_eq!(config.backer_pool(50, 3), 3);
+    }
+
+    proptest! {
+        #[test]
+        fn backer_pool_grows_no_faster_than_revenue(
+            base_percentage in 0u8..=20,
+            bonus_tiers in prop::collection::vec(bonus_tier(), 0..5),
+            before in 0u64..=MAX_AMOUNT,
+            revenue in 0u64..=MAX_AMOUNT,
+        ) {
+            let config = WaterfallConfig { tranches: Vec::new(), bonus_tiers };
+            let pool_before = config.backer_pool(base_percentage, before);
+            let pool_after = config.backer_pool(base_percentage, before + revenue);
+
+            prop_assert!(pool_after >= pool_before);
+            prop_assert!(pool_after - pool_before <= revenue);
+        }
+
+        #[test]
+        fn distribute_accounts_for_whole_pool(
+            tranches in prop::collection::vec(tranche(), 1..8),
+            pool in 0u64..=MAX_AMOUNT,
+        ) {
+            let (configs, states): (Vec<_>, Vec<_>) = tranches.into_iter().unzip();
+            let config = WaterfallConfig { tranches: configs, bonus_tiers: Vec::new() };
+
+            let (amounts, retained) = config.distribute(&states, pool);
+
+            prop_assert_eq!(amounts.len(), states.len());
+            prop_assert_eq!(amounts.iter().sum::<u64>() + retained, pool);
+        }
+
+        #[test]
+        fn distribute_respects_caps(
+            tranches in prop::collection::vec(tranche(), 1..8),
+            pool in 0u64..=MAX_AMOUNT,
+        ) {
+            let (configs, states): (Vec<_>, Vec<_>) = tranches.into_iter().unzip();
+            let config = WaterfallConfig { tranches: configs, bonus_tiers: Vec::new() };
+
+            let (amounts, _) = config.distribute(&states, pool);
+
+            for ((tranche, state), amount) in config.tranches.iter().zip(&states).zip(amounts) {
+                if let Some(cap_bps) = tranche.cap_bps {
+                    let cap = state.raised as u128 * cap_bps as u128 / BPS_SCALE as u128;
+                    prop_assert!(amount == 0 || state.received as u128 + amount as u128 <= cap);
+                }
+            }
+        }
+    }
+}